@@ -1,18 +1,170 @@
 use super::*;
 
+/// Reglas generales de compra por wallet, en tokens. Un máximo en 0 significa
+/// sin límite. Una asignación explícita de `set_allocation` reemplaza el tope por
+/// wallet, pero el mínimo y el tope por transacción siguen aplicando.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PurchaseLimits {
+    pub min_purchase: u64,
+    pub max_purchase_per_wallet: u64,
+    pub max_purchase_per_tx: u64,
+}
+
+impl PurchaseLimits {
+    pub const LEN: usize = 8 + 8 + 8;
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            (self.max_purchase_per_tx == 0 || self.min_purchase <= self.max_purchase_per_tx)
+                && (self.max_purchase_per_wallet == 0 || self.min_purchase <= self.max_purchase_per_wallet),
+            PresaleError::InvalidParameters
+        );
+        Ok(())
+    }
+
+    /// Verifica el mínimo y el tope por transacción de una compra de `amount`.
+    pub fn check_purchase(&self, amount: u64) -> Result<()> {
+        require!(amount > 0 && amount >= self.min_purchase, PresaleError::BelowMinimumPurchase);
+        require!(
+            self.max_purchase_per_tx == 0 || amount <= self.max_purchase_per_tx,
+            PresaleError::PurchaseLimitExceeded
+        );
+        Ok(())
+    }
+}
+
+impl Presale {
+    /// Máximo de tokens que puede comprar una wallet: su asignación explícita o,
+    /// si no la tiene, el límite general. `None` indica que no hay límite.
+    pub fn wallet_limit(&self, explicit_allocation: Option<u64>) -> Option<u64> {
+        match explicit_allocation {
+            Some(allocation) => Some(allocation),
+            None if self.purchase_limits.max_purchase_per_wallet > 0 => Some(self.purchase_limits.max_purchase_per_wallet),
+            None => None,
+        }
+    }
+
+    /// Fija la asignación explícita de `entry` y devuelve la anterior (0 si no
+    /// tenía). Las asignaciones nuevas respetan `max_entries`.
+    pub fn set_explicit_allocation(&mut self, entry: &mut WhitelistEntry, allocation: u64) -> Result<u64> {
+        if entry.allocation.is_none() {
+            require!(self.has_room_for_entry(self.allocation_count), PresaleError::InsufficientSpace);
+            self.allocation_count = self.allocation_count.checked_add(1).ok_or(PresaleError::CalculationError)?;
+        }
+        Ok(entry.allocation.replace(allocation).unwrap_or(0))
+    }
+
+    /// Quita la asignación explícita de `entry`, que vuelve al límite general.
+    pub fn remove_explicit_allocation(&mut self, entry: &mut WhitelistEntry) -> Option<u64> {
+        let previous_allocation = entry.allocation.take();
+        if previous_allocation.is_some() {
+            self.allocation_count = self.allocation_count.saturating_sub(1);
+        }
+        previous_allocation
+    }
+}
+
 pub fn set_allocation(ctx: Context<SetAllocation>, user: Pubkey, allocation: u64) -> Result<()> {
-    let sale = &mut ctx.accounts.sale;
-    if let Some((_, existing_allocation)) = sale.allocations.iter_mut().find(|(pubkey, _)| pubkey == &user) {
-        *existing_allocation = allocation;
-    } else {
-        sale.allocations.push((user, allocation));
+    // Quien ya compró no puede quedar con una asignación menor a lo comprado
+    let sale_key = ctx.accounts.sale.key();
+    let tokens_purchased = tokens_purchased(&ctx.accounts.purchase_record, &sale_key, &user, ctx.program_id)?;
+    require!(allocation >= tokens_purchased, PresaleError::AllocationBelowPurchased);
+
+    let whitelist_entry = &mut ctx.accounts.whitelist_entry;
+    if whitelist_entry.is_new() {
+        whitelist_entry.presale = sale_key;
+        whitelist_entry.user = user;
+        whitelist_entry.bump = ctx.bumps.whitelist_entry;
     }
+    let previous_allocation = ctx.accounts.sale.set_explicit_allocation(whitelist_entry, allocation)?;
+
+    emit!(AllocationUpdated {
+        presale: sale_key,
+        user,
+        previous_allocation,
+        allocation,
+        removed: false,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Los límites afectan a los compradores: igual que los parámetros que pasan
+/// por propuesta, solo pueden cambiarse antes de que empiece la venta.
+pub fn set_purchase_limits(ctx: Context<SetPurchaseLimits>, purchase_limits: PurchaseLimits) -> Result<()> {
+    purchase_limits.validate()?;
+    let sale = &mut ctx.accounts.sale;
+    let current_time = Clock::get()?.unix_timestamp;
+    require!(current_time < sale.start_time, PresaleError::PresaleAlreadyStarted);
+    sale.purchase_limits = purchase_limits;
+
+    emit!(PurchaseLimitsUpdated {
+        presale: sale.key(),
+        authority: ctx.accounts.admin.key(),
+        purchase_limits,
+        timestamp: current_time,
+    });
     Ok(())
 }
 
 #[derive(Accounts)]
+#[instruction(user: Pubkey)]
 pub struct SetAllocation<'info> {
     #[account(mut)]
     pub sale: Account<'info, Presale>,
+    #[account(mut)]
     pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Operator) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = WhitelistEntry::LEN,
+        seeds = [WhitelistEntry::SEED, sale.key().as_ref(), user.as_ref()],
+        bump,
+    )]
+    pub whitelist_entry: Account<'info, WhitelistEntry>,
+    /// CHECK: PDA de compra de `user`; puede no existir todavía
+    #[account(seeds = [PurchaseRecord::SEED, sale.key().as_ref(), user.as_ref()], bump)]
+    pub purchase_record: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPurchaseLimits<'info> {
+    #[account(mut)]
+    pub sale: Account<'info, Presale>,
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Operator) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_reject_new_wallets_at_max_entries() {
+        let mut presale = Presale { max_entries: 1, ..Default::default() };
+        let mut first = WhitelistEntry::default();
+        let mut second = WhitelistEntry::default();
+        assert_eq!(presale.set_explicit_allocation(&mut first, 100).unwrap(), 0);
+        assert!(presale.set_explicit_allocation(&mut second, 100).is_err());
+        assert_eq!(second.allocation, None);
+        assert_eq!(presale.allocation_count, 1);
+
+        // Cambiar una asignación existente no ocupa otro lugar
+        assert_eq!(presale.set_explicit_allocation(&mut first, 200).unwrap(), 100);
+        assert_eq!(presale.remove_explicit_allocation(&mut first), Some(200));
+        assert_eq!(presale.allocation_count, 0);
+        assert_eq!(presale.set_explicit_allocation(&mut second, 100).unwrap(), 0);
+    }
 }
@@ -0,0 +1,216 @@
+use anchor_lang::prelude::*;
+use crate::{Presale, PresaleError, PresaleStatus, PurchaseRecord, Role, RoleAssignment};
+use crate::events::{
+    BeneficiaryTransferApproved, BeneficiaryTransferCancelled, BeneficiaryTransferProposed, BeneficiaryTransferred,
+};
+
+/// Propuesta de traspaso de los derechos de un comprador (compra, vesting y lo
+/// ya reclamado) a otra wallet. La crea el beneficiario actual y la acepta el nuevo.
+#[account]
+#[derive(Default)]
+pub struct BeneficiaryTransfer {
+    pub presale: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    /// Solo se exige si la preventa requiere aprobación de un operador.
+    pub approved: bool,
+    pub proposed_at: i64,
+    pub bump: u8,
+}
+
+impl BeneficiaryTransfer {
+    pub const SEED: &'static [u8] = b"beneficiary_transfer";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // presale
+        32 + // from
+        32 + // to
+        1 + // approved
+        8 + // proposed_at
+        1; // bump
+}
+
+#[derive(Accounts)]
+pub struct ProposeBeneficiaryTransfer<'info> {
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+    #[account(
+        seeds = [PurchaseRecord::SEED, presale.key().as_ref(), beneficiary.key().as_ref()],
+        bump = purchase_record.bump,
+    )]
+    pub purchase_record: Account<'info, PurchaseRecord>,
+    #[account(
+        init,
+        payer = beneficiary,
+        space = BeneficiaryTransfer::LEN,
+        seeds = [BeneficiaryTransfer::SEED, presale.key().as_ref(), beneficiary.key().as_ref()],
+        bump,
+    )]
+    pub beneficiary_transfer: Account<'info, BeneficiaryTransfer>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveBeneficiaryTransfer<'info> {
+    pub presale: Account<'info, Presale>,
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, presale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Operator) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+    #[account(
+        mut,
+        seeds = [BeneficiaryTransfer::SEED, presale.key().as_ref(), beneficiary_transfer.from.as_ref()],
+        bump = beneficiary_transfer.bump,
+    )]
+    pub beneficiary_transfer: Account<'info, BeneficiaryTransfer>,
+}
+
+#[derive(Accounts)]
+pub struct CancelBeneficiaryTransfer<'info> {
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+    #[account(
+        mut,
+        close = beneficiary,
+        seeds = [BeneficiaryTransfer::SEED, presale.key().as_ref(), beneficiary.key().as_ref()],
+        bump = beneficiary_transfer.bump,
+    )]
+    pub beneficiary_transfer: Account<'info, BeneficiaryTransfer>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptBeneficiaryTransfer<'info> {
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub new_beneficiary: Signer<'info>,
+    /// CHECK: Beneficiario anterior; recibe la renta de las cuentas que se cierran
+    #[account(mut, address = beneficiary_transfer.from @ PresaleError::InvalidBeneficiary)]
+    pub previous_beneficiary: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        close = previous_beneficiary,
+        seeds = [BeneficiaryTransfer::SEED, presale.key().as_ref(), beneficiary_transfer.from.as_ref()],
+        bump = beneficiary_transfer.bump,
+        constraint = beneficiary_transfer.to == new_beneficiary.key() @ PresaleError::InvalidBeneficiary,
+    )]
+    pub beneficiary_transfer: Account<'info, BeneficiaryTransfer>,
+    #[account(
+        mut,
+        close = previous_beneficiary,
+        seeds = [PurchaseRecord::SEED, presale.key().as_ref(), previous_beneficiary.key().as_ref()],
+        bump = previous_purchase_record.bump,
+    )]
+    pub previous_purchase_record: Account<'info, PurchaseRecord>,
+    /// El nuevo beneficiario no puede tener compras propias en esta preventa
+    #[account(
+        init,
+        payer = new_beneficiary,
+        space = PurchaseRecord::LEN,
+        seeds = [PurchaseRecord::SEED, presale.key().as_ref(), new_beneficiary.key().as_ref()],
+        bump,
+    )]
+    pub purchase_record: Account<'info, PurchaseRecord>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Solo tras el cierre de la venta: durante la venta, traspasar la compra
+/// dejaría a la wallet original con su tope por wallet intacto.
+fn require_sale_closed(presale: &Presale) -> Result<()> {
+    require!(
+        presale.status != PresaleStatus::Active || Clock::get()?.unix_timestamp > presale.end_time,
+        PresaleError::PresaleNotEnded
+    );
+    Ok(())
+}
+
+pub fn propose_beneficiary_transfer(ctx: Context<ProposeBeneficiaryTransfer>, new_beneficiary: Pubkey) -> Result<()> {
+    require_sale_closed(&ctx.accounts.presale)?;
+    let beneficiary = ctx.accounts.beneficiary.key();
+    require!(
+        new_beneficiary != beneficiary && new_beneficiary != Pubkey::default(),
+        PresaleError::InvalidBeneficiary
+    );
+
+    let beneficiary_transfer = &mut ctx.accounts.beneficiary_transfer;
+    beneficiary_transfer.presale = ctx.accounts.presale.key();
+    beneficiary_transfer.from = beneficiary;
+    beneficiary_transfer.to = new_beneficiary;
+    beneficiary_transfer.approved = false;
+    beneficiary_transfer.proposed_at = Clock::get()?.unix_timestamp;
+    beneficiary_transfer.bump = ctx.bumps.beneficiary_transfer;
+
+    emit!(BeneficiaryTransferProposed {
+        presale: beneficiary_transfer.presale,
+        from: beneficiary,
+        to: new_beneficiary,
+        requires_approval: ctx.accounts.presale.transfer_requires_approval,
+        timestamp: beneficiary_transfer.proposed_at,
+    });
+    Ok(())
+}
+
+pub fn approve_beneficiary_transfer(ctx: Context<ApproveBeneficiaryTransfer>) -> Result<()> {
+    let beneficiary_transfer = &mut ctx.accounts.beneficiary_transfer;
+    beneficiary_transfer.approved = true;
+
+    emit!(BeneficiaryTransferApproved {
+        presale: beneficiary_transfer.presale,
+        from: beneficiary_transfer.from,
+        to: beneficiary_transfer.to,
+        authority: ctx.accounts.admin.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+pub fn cancel_beneficiary_transfer(ctx: Context<CancelBeneficiaryTransfer>) -> Result<()> {
+    emit!(BeneficiaryTransferCancelled {
+        presale: ctx.accounts.beneficiary_transfer.presale,
+        from: ctx.accounts.beneficiary_transfer.from,
+        to: ctx.accounts.beneficiary_transfer.to,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Mueve al nuevo beneficiario el registro de compra, con su vesting y lo ya
+/// reclamado, de modo que nada pueda reclamarse dos veces.
+pub fn accept_beneficiary_transfer(ctx: Context<AcceptBeneficiaryTransfer>) -> Result<()> {
+    let presale = &ctx.accounts.presale;
+    require_sale_closed(presale)?;
+    require!(
+        ctx.accounts.beneficiary_transfer.approved || !presale.transfer_requires_approval,
+        PresaleError::TransferNotApproved
+    );
+
+    let previous_beneficiary = ctx.accounts.previous_beneficiary.key();
+    let new_beneficiary = ctx.accounts.new_beneficiary.key();
+
+    // El registro anterior se cierra al terminar la instrucción
+    let previous_record = &ctx.accounts.previous_purchase_record;
+    let purchase_record = &mut ctx.accounts.purchase_record;
+    purchase_record.presale = presale.key();
+    purchase_record.buyer = new_beneficiary;
+    purchase_record.tokens_purchased = previous_record.tokens_purchased;
+    purchase_record.amount_contributed = previous_record.amount_contributed;
+    purchase_record.refund_claimed = previous_record.refund_claimed;
+    purchase_record.phase_purchases = previous_record.phase_purchases;
+    purchase_record.vesting = previous_record.vesting.clone();
+    purchase_record.bump = ctx.bumps.purchase_record;
+
+    emit!(BeneficiaryTransferred {
+        presale: presale.key(),
+        from: previous_beneficiary,
+        to: new_beneficiary,
+        tokens_purchased: purchase_record.tokens_purchased,
+        vesting_amount: purchase_record.vesting.amount,
+        claimed_amount: purchase_record.vesting.claimed_amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
@@ -1,6 +1,8 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer as TokenTransfer};
-use crate::{Presale, PresaleError, GlobalStats};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{Presale, PresaleError, PresaleStatus, GlobalStats, ProgramConfig, PurchaseRecord};
+use crate::escrow::{pay_from_escrow, transfer_tokens};
+use crate::events::TokensClaimed;
 
 #[derive(Accounts)]
 pub struct ClaimTokens<'info> {
@@ -8,68 +10,165 @@ pub struct ClaimTokens<'info> {
     pub presale: Account<'info, Presale>,
     #[account(mut)]
     pub user: Signer<'info>,
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        constraint = !config.circuit_breaker.claims_paused @ PresaleError::ProtocolPaused,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(
+        mut,
+        seeds = [PurchaseRecord::SEED, presale.key().as_ref(), user.key().as_ref()],
+        bump = purchase_record.bump,
+    )]
+    pub purchase_record: Account<'info, PurchaseRecord>,
     #[account(mut)]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = presale.token_vault)]
+    pub token_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = presale.sale_token @ PresaleError::InvalidSaleMint)]
+    pub sale_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA que firma las transferencias del token_vault y custodia los pagos
+    #[account(mut, seeds = [presale.key().as_ref()], bump = presale.bump)]
+    pub sale_authority: AccountInfo<'info>,
+    /// Solo en modo `RaiseMode::SplToken`: recibe la diferencia de precio a devolver
     #[account(mut)]
-    pub token_vault: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+    pub user_raise_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo en modo `RaiseMode::SplToken`: bóveda de recaudación de la preventa
+    #[account(mut)]
+    pub raise_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo en modo `RaiseMode::SplToken`: mint del token de recaudación
+    pub raise_mint: Option<InterfaceAccount<'info, Mint>>,
+    /// Programa del token de venta (clásico o Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+    /// Solo en modo `RaiseMode::SplToken`: programa del token de recaudación
+    pub raise_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
     #[account(mut)]
     pub global_stats: Account<'info, GlobalStats>,
 }
 
-pub fn claim_tokens(ctx: Context<ClaimTokens>) -> Result<()> {
-    let presale = &mut ctx.accounts.presale;
+pub fn claim_tokens<'info>(ctx: Context<'_, '_, '_, 'info, ClaimTokens<'info>>) -> Result<()> {
     let user_key = ctx.accounts.user.key();
-    let global_stats = &mut ctx.accounts.global_stats;
+    let current_time = Clock::get()?.unix_timestamp;
 
-    // Obtener la cantidad a reclamar
-    let amount = presale.vestings.iter()
-        .find(|(pubkey, _)| pubkey == &user_key)
-        .map(|(_, vesting)| vesting.amount)
-        .unwrap_or(0);
+    // Los tokens solo se liberan una vez liquidada la preventa: hasta entonces una
+    // subasta todavía puede terminar por debajo del soft cap y pasar a reembolsos
+    let presale = &mut ctx.accounts.presale;
+    presale.refresh_status(current_time);
+    require!(presale.status != PresaleStatus::Failed, PresaleError::SoftCapNotReached);
+    require!(presale.status == PresaleStatus::Finalized, PresaleError::PresaleNotEnded);
 
-    require!(amount > 0, PresaleError::NoTokensToClaim);
+    // Devolver primero la diferencia pendiente si la preventa liquidó a un precio uniforme
+    let refund = ctx.accounts.purchase_record.refund_due(&ctx.accounts.presale)?;
+    if refund > 0 {
+        ctx.accounts.purchase_record.refund_claimed = true;
+        pay_from_escrow(
+            &ctx.accounts.presale,
+            &ctx.accounts.sale_authority,
+            &ctx.accounts.user.to_account_info(),
+            ctx.accounts.user_raise_account.as_ref(),
+            ctx.accounts.raise_vault.as_ref(),
+            ctx.accounts.raise_mint.as_ref(),
+            ctx.accounts.raise_token_program.as_ref(),
+            &ctx.accounts.system_program,
+            ctx.remaining_accounts,
+            refund,
+        )?;
+    }
 
-    // Transferir tokens del token_vault a la cuenta del usuario
-    let presale_key = presale.key();
-    let authority_seeds = &[presale_key.as_ref(), &[presale.bump]];
-    let signer = &[&authority_seeds[..]];
-    
-    let cpi_accounts = TokenTransfer {
-        from: ctx.accounts.token_vault.to_account_info(),
-        to: ctx.accounts.user_token_account.to_account_info(),
-        authority: presale.to_account_info(),
+    // Un reclamo que solo liquida la diferencia de precio también es válido
+    let amount = match releasable_amount(&ctx.accounts.presale, &ctx.accounts.purchase_record, current_time) {
+        Ok(amount) => amount,
+        Err(_) if refund > 0 => 0,
+        Err(err) => return Err(err),
     };
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-    token::transfer(cpi_ctx, amount)?;
-
-    // Actualizar el estado de reclamación del usuario
-    if let Some((_pubkey, ref mut claimed)) = presale.claims.iter_mut().find(|(pubkey, _)| pubkey == &user_key) {
-        *claimed = true;
-    } else {
-        require!(
-            presale.claims.len() < presale.max_entries as usize,
-            PresaleError::InsufficientSpace
-        );
-        presale.claims.push((user_key, true));
+
+    let presale = &ctx.accounts.presale;
+    let vesting_info = &mut ctx.accounts.purchase_record.vesting;
+    let mut received = 0;
+    if amount > 0 {
+        vesting_info.claimed_amount = vesting_info.claimed_amount
+            .checked_add(amount)
+            .ok_or(PresaleError::CalculationError)?;
+
+        // Transferir tokens del token_vault a la cuenta del usuario. `amount` sale
+        // completo de la bóveda y la comisión del mint, si la hay, la asume el
+        // comprador: la bóveda solo custodia lo vendido, así que no puede pagarla.
+        // Se registra aparte lo que realmente llegó a su cuenta
+        let balance_before = ctx.accounts.user_token_account.amount;
+        let presale_key = presale.key();
+        let authority_seeds = &[presale_key.as_ref(), &[presale.bump]];
+        let signer = &[&authority_seeds[..]];
+
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.sale_mint,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.sale_authority,
+            ctx.remaining_accounts,
+            amount,
+            signer,
+        )?;
+
+        ctx.accounts.user_token_account.reload()?;
+        received = ctx.accounts.user_token_account.amount.saturating_sub(balance_before);
+        vesting_info.received_amount = vesting_info.received_amount
+            .checked_add(received)
+            .ok_or(PresaleError::CalculationError)?;
     }
 
     // Actualizar estadísticas globales
+    let global_stats = &mut ctx.accounts.global_stats;
     if let Some(presale_info) = global_stats.presales.iter_mut().find(|p| p.id == presale.id) {
         presale_info.total_raised = presale.total_raised;
     }
 
-    // Verificar si el tiempo de vesting ha pasado
-    let current_time = Clock::get()?.unix_timestamp;
-    let vesting_info = presale.vestings.iter()
-        .find(|(pubkey, _)| pubkey == &user_key)
-        .map(|(_, info)| info)
-        .ok_or(PresaleError::NoVestingFound)?;
+    emit!(TokensClaimed {
+        presale: presale.key(),
+        user: user_key,
+        amount,
+        claimed_amount: vesting_info.claimed_amount,
+        vesting_amount: vesting_info.amount,
+        received,
+        refund,
+        timestamp: current_time,
+    });
+
+    Ok(())
+}
+
+/// Porción desbloqueada del vesting del usuario que aún no ha reclamado.
+fn releasable_amount(presale: &Presale, purchase_record: &PurchaseRecord, current_time: i64) -> Result<u64> {
+    require!(purchase_record.has_vesting(), PresaleError::NoVestingFound);
+    let vesting_info = &purchase_record.vesting;
+
+    // El monto a reclamar nunca puede superar lo asignado
+    require!(
+        vesting_info.amount <= purchase_record.allocated_tokens(presale)?,
+        PresaleError::VestingExceedsPurchase
+    );
+
+    // Verificar si el tiempo de vesting ha comenzado
     require!(
         current_time >= vesting_info.release_time,
         PresaleError::VestingPeriodNotEnded
     );
+    require!(
+        vesting_info.claimed_amount < vesting_info.amount,
+        PresaleError::AlreadyClaimed
+    );
 
-    Ok(())
+    // Calcular la porción desbloqueada que aún no se ha reclamado
+    let vested = presale.vesting_schedule.vested_amount(
+        vesting_info.amount,
+        vesting_info.release_time,
+        presale.vesting_end_time,
+        current_time,
+    )?;
+    let amount = vested.saturating_sub(vesting_info.claimed_amount);
+    require!(amount > 0, PresaleError::NoTokensToClaim);
+
+    Ok(amount)
 }
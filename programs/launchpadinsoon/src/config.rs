@@ -0,0 +1,90 @@
+use anchor_lang::prelude::*;
+use crate::PresaleError;
+use crate::events::{ConfigAdminChanged, ConfigInitialized};
+
+/// Interruptores globales que detienen una operación en todas las preventas.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct CircuitBreaker {
+    pub buys_paused: bool,
+    pub claims_paused: bool,
+    pub launches_paused: bool,
+}
+
+impl CircuitBreaker {
+    pub const LEN: usize = 1 + 1 + 1;
+}
+
+#[account]
+#[derive(Default)]
+pub struct ProgramConfig {
+    pub admin: Pubkey,
+    pub bump: u8,
+    pub circuit_breaker: CircuitBreaker,
+}
+
+impl ProgramConfig {
+    pub const SEED: &'static [u8] = b"config";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // admin
+        1 + // bump
+        CircuitBreaker::LEN; // circuit_breaker
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = ProgramConfig::LEN,
+        seeds = [ProgramConfig::SEED],
+        bump,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ PresaleError::Unauthorized)]
+    pub program: Program<'info, crate::program::Launchpadinsoon>,
+    /// Solo la autoridad de actualización del programa puede crear la configuración
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ PresaleError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetConfigAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+}
+
+pub fn initialize_config(ctx: Context<InitializeConfig>) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    config.admin = ctx.accounts.admin.key();
+    config.bump = ctx.bumps.config;
+    config.circuit_breaker = CircuitBreaker::default();
+
+    emit!(ConfigInitialized {
+        admin: config.admin,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+pub fn set_config_admin(ctx: Context<SetConfigAdmin>, new_admin: Pubkey) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    let previous_admin = config.admin;
+    config.admin = new_admin;
+
+    emit!(ConfigAdminChanged {
+        previous_admin,
+        new_admin,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{Presale, PresaleError, RaiseMode};
+
+/// Monto a enviar para que el destino reciba exactamente `net_amount` tras la
+/// comisión de transferencia del mint.
+pub fn gross_amount_for(mint: &InterfaceAccount<Mint>, net_amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    let fee = match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(fee_config) => fee_config
+            .calculate_inverse_epoch_fee(Clock::get()?.epoch, net_amount)
+            .ok_or(PresaleError::CalculationError)?,
+        Err(_) => 0,
+    };
+    Ok(net_amount.checked_add(fee).ok_or(PresaleError::CalculationError)?)
+}
+
+/// `transfer_checked` bajo cualquiera de los dos programas de token. Si el mint
+/// tiene transfer hook, sus cuentas extra se toman de `hook_accounts`.
+pub fn transfer_tokens<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    authority: &AccountInfo<'info>,
+    hook_accounts: &[AccountInfo<'info>],
+    amount: u64,
+    signer: &[&[&[u8]]],
+) -> Result<()> {
+    spl_token_2022::onchain::invoke_transfer_checked(
+        &token_program.key(),
+        from.to_account_info(),
+        mint.to_account_info(),
+        to.to_account_info(),
+        authority.clone(),
+        hook_accounts,
+        amount,
+        mint.decimals,
+        signer,
+    )
+    .map_err(Into::into)
+}
+
+/// Acredita exactamente `net_amount` en una bóveda de la preventa: quien deposita
+/// paga además la comisión de transferencia. Se comprueba contra el saldo real
+/// de la bóveda para que la contabilidad nunca supere lo custodiado.
+pub fn deposit_tokens<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    vault: &mut InterfaceAccount<'info, TokenAccount>,
+    authority: &AccountInfo<'info>,
+    hook_accounts: &[AccountInfo<'info>],
+    net_amount: u64,
+) -> Result<()> {
+    let balance_before = vault.amount;
+    let gross_amount = gross_amount_for(mint, net_amount)?;
+    transfer_tokens(token_program, from, mint, vault, authority, hook_accounts, gross_amount, &[])?;
+
+    vault.reload()?;
+    let received = vault.amount.checked_sub(balance_before).ok_or(PresaleError::CalculationError)?;
+    require!(received >= net_amount, PresaleError::TransferAmountMismatch);
+    Ok(())
+}
+
+/// Paga `amount` desde la custodia de la preventa en su moneda de recaudación:
+/// lamports de la autoridad PDA en modo SOL o la bóveda de recaudación en modo SPL.
+/// En modo SPL el destinatario recibe `amount` menos la comisión del mint, si la hay.
+pub fn pay_from_escrow<'info>(
+    presale: &Account<'info, Presale>,
+    sale_authority: &AccountInfo<'info>,
+    recipient: &AccountInfo<'info>,
+    recipient_raise_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    raise_vault: Option<&InterfaceAccount<'info, TokenAccount>>,
+    raise_mint: Option<&InterfaceAccount<'info, Mint>>,
+    token_program: Option<&Interface<'info, TokenInterface>>,
+    system_program: &Program<'info, System>,
+    hook_accounts: &[AccountInfo<'info>],
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let presale_key = presale.key();
+    let authority_seeds = &[presale_key.as_ref(), &[presale.bump]];
+    let signer = &[&authority_seeds[..]];
+
+    match presale.raise_mode {
+        RaiseMode::NativeSol => {
+            let cpi_context = CpiContext::new_with_signer(
+                system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: sale_authority.clone(),
+                    to: recipient.clone(),
+                },
+                signer,
+            );
+            anchor_lang::system_program::transfer(cpi_context, amount)
+        }
+        RaiseMode::SplToken => {
+            let recipient_raise_account = recipient_raise_account.ok_or(PresaleError::InvalidRaiseToken)?;
+            let raise_vault = raise_vault.ok_or(PresaleError::InvalidRaiseVault)?;
+            let raise_mint = raise_mint.ok_or(PresaleError::InvalidRaiseToken)?;
+            let token_program = token_program.ok_or(PresaleError::InvalidRaiseVault)?;
+            require!(
+                recipient_raise_account.mint == presale.raise_token
+                    && recipient_raise_account.owner == recipient.key(),
+                PresaleError::InvalidRaiseToken
+            );
+            require!(raise_mint.key() == presale.raise_token, PresaleError::InvalidRaiseToken);
+            require!(raise_vault.key() == presale.raise_vault, PresaleError::InvalidRaiseVault);
+
+            transfer_tokens(
+                token_program,
+                raise_vault,
+                raise_mint,
+                recipient_raise_account,
+                sale_authority,
+                hook_accounts,
+                amount,
+                signer,
+            )
+        }
+    }
+}
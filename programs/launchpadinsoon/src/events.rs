@@ -0,0 +1,464 @@
+//! Eventos emitidos en cada cambio de estado, para que los indexadores no tengan
+//! que comparar la cuenta `Presale` completa tras cada transacción.
+
+use anchor_lang::prelude::*;
+use crate::{CircuitBreaker, GrantCategory, LiquidityLockConfig, LockedAsset, ParameterUpdate, PurchaseLimits, RaiseMode, Role, SaleMode, StakingTier, VestingSchedule};
+
+#[event]
+pub struct ConfigInitialized {
+    pub admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConfigAdminChanged {
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProtocolPauseChanged {
+    pub admin: Pubkey,
+    /// Interruptores indicados en la instrucción.
+    pub scope: CircuitBreaker,
+    /// Estado resultante de todos los interruptores.
+    pub circuit_breaker: CircuitBreaker,
+    pub paused: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RoleChanged {
+    pub presale: Pubkey,
+    pub holder: Pubkey,
+    pub authority: Pubkey,
+    pub role: Role,
+    pub granted: bool,
+    /// Máscara de roles resultante del titular.
+    pub roles: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PresaleCreated {
+    pub presale: Pubkey,
+    pub id: u64,
+    pub creator: Pubkey,
+    pub sale_token: Pubkey,
+    pub raise_token: Pubkey,
+    pub raise_mode: RaiseMode,
+    pub sale_mode: SaleMode,
+    pub total_tokens: u64,
+    pub price: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub vesting_end_time: i64,
+    pub raise_goal: u64,
+    pub max_entries: u64,
+    pub purchase_limits: PurchaseLimits,
+    pub phase_count: u8,
+    pub tokens_per_tier_weight: u64,
+    pub liquidity_lock: LiquidityLockConfig,
+    pub transfer_requires_approval: bool,
+    /// Comisión de creación cobrada por el protocolo, en lamports.
+    pub launch_fee: u64,
+    pub raise_fee_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WhitelistUpdated {
+    pub presale: Pubkey,
+    pub user: Pubkey,
+    pub added: bool,
+    pub whitelist_size: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MerkleRootUpdated {
+    pub presale: Pubkey,
+    pub merkle_root: [u8; 32],
+    pub merkle_allocations: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AllocationUpdated {
+    pub presale: Pubkey,
+    pub user: Pubkey,
+    pub previous_allocation: u64,
+    pub allocation: u64,
+    /// `true` si se quitó la asignación explícita; `allocation` queda en 0.
+    pub removed: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PurchaseLimitsUpdated {
+    pub presale: Pubkey,
+    pub authority: Pubkey,
+    pub purchase_limits: PurchaseLimits,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensPurchased {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub cost: u64,
+    /// Totales del comprador tras la compra.
+    pub buyer_tokens_purchased: u64,
+    pub buyer_amount_contributed: u64,
+    /// Totales de la preventa tras la compra.
+    pub tokens_sold: u64,
+    pub total_raised: u64,
+    pub total_investors: u32,
+    /// Fase en la que se hizo la compra, si la preventa usa fases.
+    pub phase: Option<u8>,
+    pub referrer: Option<Pubkey>,
+    pub referral_reward: u64,
+    /// Nivel de staking que dio acceso o asignación a la compra, si lo hubo.
+    pub stake_tier: Option<u8>,
+    /// Boleto ganador del sorteo usado en la compra, si lo hubo.
+    pub lottery_ticket: Option<u32>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VestingCreated {
+    pub presale: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub release_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensClaimed {
+    pub presale: Pubkey,
+    pub user: Pubkey,
+    /// Tokens que salieron del token_vault en este reclamo.
+    pub amount: u64,
+    pub claimed_amount: u64,
+    pub vesting_amount: u64,
+    /// Lo que llegó a la cuenta del comprador tras la comisión del mint.
+    pub received: u64,
+    /// Diferencia de precio o depósito no usado devuelto en el mismo reclamo.
+    pub refund: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Refunded {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SaleTokensReclaimed {
+    pub presale: Pubkey,
+    pub creator: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PresaleFinalized {
+    pub presale: Pubkey,
+    pub authority: Pubkey,
+    /// Lo que recibió el creador, ya descontadas la comisión del protocolo y la
+    /// parte bloqueada como liquidez.
+    pub proceeds: u64,
+    pub protocol_fee: u64,
+    /// Reservado en custodia para que lo cobren los referidores.
+    pub referral_rewards: u64,
+    pub tokens_sold: u64,
+    pub total_raised: u64,
+    pub unsold_tokens: u64,
+    pub clearing_price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SalePauseChanged {
+    pub presale: Pubkey,
+    pub authority: Pubkey,
+    pub paused: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ParametersUpdated {
+    pub presale: Pubkey,
+    pub authority: Pubkey,
+    pub update: ParameterUpdate,
+    /// `true` si se aplicó al ejecutar una propuesta con timelock.
+    pub from_proposal: bool,
+    /// Tokens devueltos al creador al reducir `total_tokens`.
+    pub returned_tokens: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ParameterUpdateProposed {
+    pub presale: Pubkey,
+    pub authority: Pubkey,
+    pub update: ParameterUpdate,
+    pub executable_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ParameterUpdateCancelled {
+    pub presale: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GlobalStatsUpdated {
+    /// Preventa que originó la actualización, si la hubo.
+    pub presale_id: Option<u64>,
+    pub total_presales: u32,
+    pub total_raised: u64,
+    pub total_investors: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeeConfigUpdated {
+    pub admin: Pubkey,
+    pub listing_fee: u64,
+    pub weekly_fee: u64,
+    pub raise_fee_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeeExemptionChanged {
+    pub creator: Pubkey,
+    pub exempt: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub admin: Pubkey,
+    /// `None` si se retiraron lamports.
+    pub mint: Option<Pubkey>,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferrerRegistered {
+    pub presale: Pubkey,
+    pub referrer: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralRewardsClaimed {
+    pub presale: Pubkey,
+    pub referrer: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakePoolUpdated {
+    pub admin: Pubkey,
+    pub stake_mint: Pubkey,
+    pub lock_duration: i64,
+    pub tiers: Vec<StakingTier>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensStaked {
+    pub owner: Pubkey,
+    pub amount: u64,
+    /// Saldo en stake tras el depósito.
+    pub staked_amount: u64,
+    pub staked_since: i64,
+    pub locked_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensUnstaked {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub staked_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakeLockExtended {
+    pub owner: Pubkey,
+    pub presale: Pubkey,
+    pub locked_until: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LotteryCreated {
+    pub presale: Pubkey,
+    pub registration_start: i64,
+    pub registration_end: i64,
+    pub winner_count: u32,
+    pub allocation_per_winner: u64,
+    pub commitment: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LotteryRegistered {
+    pub presale: Pubkey,
+    pub user: Pubkey,
+    pub ticket_index: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LotteryRegistrationClosed {
+    pub presale: Pubkey,
+    pub registrants: u32,
+    pub draw_slot: u64,
+    pub timestamp: i64,
+}
+
+/// Incluye todo lo necesario para reproducir el sorteo fuera de la cadena.
+#[event]
+pub struct LotteryDrawn {
+    pub presale: Pubkey,
+    pub secret: [u8; 32],
+    pub slot_hash: [u8; 32],
+    pub seed: [u8; 32],
+    pub stride: u64,
+    pub offset: u64,
+    pub registrants: u32,
+    pub winners: u32,
+    pub timestamp: i64,
+}
+
+/// El secreto no se reveló a tiempo: todos los registrados ganan a prorrata.
+#[event]
+pub struct LotteryDrawExpired {
+    pub presale: Pubkey,
+    pub draw_slot: u64,
+    pub registrants: u32,
+    pub allocation_per_registrant: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityLocked {
+    pub presale: Pubkey,
+    pub creator: Pubkey,
+    pub locked_sale_tokens: u64,
+    pub locked_proceeds: u64,
+    pub unlock_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LpTokensLocked {
+    pub presale: Pubkey,
+    pub lp_mint: Pubkey,
+    pub amount: u64,
+    /// Total de LP tokens bloqueados tras el depósito.
+    pub locked_lp_tokens: u64,
+    pub unlock_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LiquidityWithdrawn {
+    pub presale: Pubkey,
+    pub creator: Pubkey,
+    pub asset: LockedAsset,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TeamGrantCreated {
+    pub presale: Pubkey,
+    pub beneficiary: Pubkey,
+    pub category: GrantCategory,
+    pub amount: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub schedule: VestingSchedule,
+    pub revocable: bool,
+    /// `Pubkey::default()` si la asignación no es revocable.
+    pub treasury: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TeamGrantClaimed {
+    pub presale: Pubkey,
+    pub beneficiary: Pubkey,
+    pub amount: u64,
+    pub claimed_amount: u64,
+    pub grant_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TeamGrantRevoked {
+    pub presale: Pubkey,
+    pub beneficiary: Pubkey,
+    pub authority: Pubkey,
+    /// Lo que conserva el beneficiario: todo lo desbloqueado hasta la revocación.
+    pub vested_amount: u64,
+    /// Lo devuelto a la tesorería.
+    pub revoked_amount: u64,
+    pub treasury: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BeneficiaryTransferProposed {
+    pub presale: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub requires_approval: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BeneficiaryTransferApproved {
+    pub presale: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BeneficiaryTransferCancelled {
+    pub presale: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BeneficiaryTransferred {
+    pub presale: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub tokens_purchased: u64,
+    pub vesting_amount: u64,
+    pub claimed_amount: u64,
+    pub timestamp: i64,
+}
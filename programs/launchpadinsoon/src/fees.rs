@@ -0,0 +1,324 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{PresaleError, ProgramConfig};
+use crate::escrow::transfer_tokens;
+use crate::events::{FeeConfigUpdated, FeeExemptionChanged, FeesWithdrawn};
+use crate::vesting::BPS_DENOMINATOR;
+
+/// Tope de la comisión sobre lo recaudado, para proteger a los creadores.
+pub const MAX_RAISE_FEE_BPS: u16 = 1_000;
+
+/// Semilla de la bóveda PDA del protocolo que recibe todas las comisiones.
+/// En modo SPL la comisión va a una cuenta de token cuyo dueño es esta PDA.
+pub const FEE_VAULT_SEED: &[u8] = b"fee_vault";
+
+/// Tarifas que cobra el protocolo; las edita el admin del `ProgramConfig`.
+#[account]
+#[derive(Default)]
+pub struct FeeConfig {
+    /// Monto fijo en lamports al crear una preventa.
+    pub listing_fee: u64,
+    /// Lamports por cada semana (o fracción) de duración de la preventa.
+    pub weekly_fee: u64,
+    /// Porcentaje de lo recaudado que se retiene al liquidar, en puntos básicos.
+    pub raise_fee_bps: u16,
+    pub bump: u8,
+    pub fee_vault_bump: u8,
+}
+
+impl FeeConfig {
+    pub const SEED: &'static [u8] = b"fee_config";
+
+    pub const LEN: usize = 8 + // discriminator
+        8 + // listing_fee
+        8 + // weekly_fee
+        2 + // raise_fee_bps
+        1 + // bump
+        1; // fee_vault_bump
+
+    pub fn validate(&self) -> Result<()> {
+        require!(self.raise_fee_bps <= MAX_RAISE_FEE_BPS, PresaleError::InvalidFeeConfig);
+        Ok(())
+    }
+
+    /// Comisión de creación para una preventa de `start_time` a `end_time`.
+    pub fn launch_fee(&self, start_time: i64, end_time: i64) -> Result<u64> {
+        let duration_weeks = (end_time - start_time) / (7 * 24 * 60 * 60) + 1;
+        self.weekly_fee
+            .checked_mul(duration_weeks as u64)
+            .and_then(|weekly| weekly.checked_add(self.listing_fee))
+            .ok_or_else(|| error!(PresaleError::CalculationError))
+    }
+}
+
+/// Porción de `proceeds` que corresponde al protocolo.
+pub fn raise_fee(proceeds: u64, raise_fee_bps: u16) -> Result<u64> {
+    let fee = (proceeds as u128)
+        .checked_mul(raise_fee_bps as u128)
+        .ok_or(PresaleError::CalculationError)?
+        / BPS_DENOMINATOR as u128;
+    Ok(fee as u64)
+}
+
+/// Su existencia exime al creador de todas las comisiones del protocolo.
+#[account]
+#[derive(Default)]
+pub struct FeeExemption {
+    pub creator: Pubkey,
+    pub bump: u8,
+}
+
+impl FeeExemption {
+    pub const SEED: &'static [u8] = b"fee_exemption";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // creator
+        1; // bump
+}
+
+#[derive(Accounts)]
+pub struct InitializeFeeConfig<'info> {
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = FeeConfig::LEN,
+        seeds = [FeeConfig::SEED],
+        bump,
+    )]
+    pub fee_config: Account<'info, FeeConfig>,
+    /// CHECK: PDA del protocolo que custodia las comisiones en SOL
+    #[account(mut, seeds = [FEE_VAULT_SEED], bump)]
+    pub fee_vault: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFeeConfig<'info> {
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+    #[account(mut, seeds = [FeeConfig::SEED], bump = fee_config.bump)]
+    pub fee_config: Account<'info, FeeConfig>,
+}
+
+#[derive(Accounts)]
+pub struct GrantFeeExemption<'info> {
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    /// CHECK: Creador eximido; solo se usa como semilla
+    pub creator: UncheckedAccount<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = FeeExemption::LEN,
+        seeds = [FeeExemption::SEED, creator.key().as_ref()],
+        bump,
+    )]
+    pub fee_exemption: Account<'info, FeeExemption>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeFeeExemption<'info> {
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [FeeExemption::SEED, fee_exemption.creator.as_ref()],
+        bump = fee_exemption.bump,
+    )]
+    pub fee_exemption: Account<'info, FeeExemption>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+    #[account(seeds = [FeeConfig::SEED], bump = fee_config.bump)]
+    pub fee_config: Account<'info, FeeConfig>,
+    /// CHECK: PDA del protocolo que custodia las comisiones
+    #[account(mut, seeds = [FEE_VAULT_SEED], bump = fee_config.fee_vault_bump)]
+    pub fee_vault: AccountInfo<'info>,
+    /// CHECK: Destino de los lamports cuando se retira SOL
+    #[account(mut)]
+    pub recipient: AccountInfo<'info>,
+    /// Solo al retirar tokens: cuenta de comisiones cuyo dueño es `fee_vault`
+    #[account(mut)]
+    pub fee_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo al retirar tokens: cuenta que recibe los tokens
+    #[account(mut)]
+    pub recipient_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn initialize_fee_config(
+    ctx: Context<InitializeFeeConfig>,
+    listing_fee: u64,
+    weekly_fee: u64,
+    raise_fee_bps: u16,
+) -> Result<()> {
+    let fee_config = &mut ctx.accounts.fee_config;
+    fee_config.listing_fee = listing_fee;
+    fee_config.weekly_fee = weekly_fee;
+    fee_config.raise_fee_bps = raise_fee_bps;
+    fee_config.bump = ctx.bumps.fee_config;
+    fee_config.fee_vault_bump = ctx.bumps.fee_vault;
+    fee_config.validate()?;
+
+    // La bóveda se fondea con el mínimo de renta para poder recibir comisiones pequeñas
+    let rent_exempt_minimum = Rent::get()?.minimum_balance(0);
+    let missing = rent_exempt_minimum.saturating_sub(ctx.accounts.fee_vault.lamports());
+    if missing > 0 {
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.admin.to_account_info(),
+                to: ctx.accounts.fee_vault.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_context, missing)?;
+    }
+
+    emit!(FeeConfigUpdated {
+        admin: ctx.accounts.admin.key(),
+        listing_fee,
+        weekly_fee,
+        raise_fee_bps,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Las nuevas tarifas solo aplican a preventas creadas a partir de ahora.
+pub fn update_fee_config(
+    ctx: Context<UpdateFeeConfig>,
+    listing_fee: u64,
+    weekly_fee: u64,
+    raise_fee_bps: u16,
+) -> Result<()> {
+    let fee_config = &mut ctx.accounts.fee_config;
+    fee_config.listing_fee = listing_fee;
+    fee_config.weekly_fee = weekly_fee;
+    fee_config.raise_fee_bps = raise_fee_bps;
+    fee_config.validate()?;
+
+    emit!(FeeConfigUpdated {
+        admin: ctx.accounts.admin.key(),
+        listing_fee,
+        weekly_fee,
+        raise_fee_bps,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+pub fn grant_fee_exemption(ctx: Context<GrantFeeExemption>) -> Result<()> {
+    let fee_exemption = &mut ctx.accounts.fee_exemption;
+    fee_exemption.creator = ctx.accounts.creator.key();
+    fee_exemption.bump = ctx.bumps.fee_exemption;
+
+    emit!(FeeExemptionChanged {
+        creator: fee_exemption.creator,
+        exempt: true,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+pub fn revoke_fee_exemption(ctx: Context<RevokeFeeExemption>) -> Result<()> {
+    emit!(FeeExemptionChanged {
+        creator: ctx.accounts.fee_exemption.creator,
+        exempt: false,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Retira comisiones de la bóveda del protocolo: tokens si se pasan las cuentas
+/// de token, lamports en caso contrario.
+pub fn withdraw_fees<'info>(ctx: Context<'_, '_, '_, 'info, WithdrawFees<'info>>, amount: u64) -> Result<()> {
+    let vault_seeds = &[FEE_VAULT_SEED, &[ctx.accounts.fee_config.fee_vault_bump]];
+    let signer = &[&vault_seeds[..]];
+
+    let (mint, recipient) = match (
+        ctx.accounts.fee_token_account.as_ref(),
+        ctx.accounts.recipient_token_account.as_ref(),
+        ctx.accounts.mint.as_ref(),
+        ctx.accounts.token_program.as_ref(),
+    ) {
+        (Some(fee_token_account), Some(recipient_token_account), Some(mint), Some(token_program)) => {
+            require!(fee_token_account.owner == ctx.accounts.fee_vault.key(), PresaleError::InvalidCommissionVault);
+            transfer_tokens(
+                token_program,
+                fee_token_account,
+                mint,
+                recipient_token_account,
+                &ctx.accounts.fee_vault,
+                ctx.remaining_accounts,
+                amount,
+                signer,
+            )?;
+            (Some(mint.key()), recipient_token_account.key())
+        }
+        (None, None, None, None) => {
+            // La bóveda nunca queda por debajo del mínimo de renta
+            let rent_exempt_minimum = Rent::get()?.minimum_balance(0);
+            require!(
+                ctx.accounts.fee_vault.lamports().saturating_sub(amount) >= rent_exempt_minimum,
+                PresaleError::InsufficientFeeBalance
+            );
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.fee_vault.to_account_info(),
+                    to: ctx.accounts.recipient.to_account_info(),
+                },
+                signer,
+            );
+            anchor_lang::system_program::transfer(cpi_context, amount)?;
+            (None, ctx.accounts.recipient.key())
+        }
+        _ => return err!(PresaleError::InvalidCommissionVault),
+    };
+
+    emit!(FeesWithdrawn {
+        admin: ctx.accounts.admin.key(),
+        mint,
+        recipient,
+        amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
@@ -0,0 +1,267 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{self, Burn, Mint, TokenAccount, TokenInterface};
+use crate::{Presale, PresaleError, PresaleStatus, RaiseMode, Role, RoleAssignment, UnsoldTokenAction};
+use crate::escrow::{pay_from_escrow, transfer_tokens};
+use crate::events::{LiquidityLocked, PresaleFinalized};
+use crate::fees::{raise_fee, FEE_VAULT_SEED};
+use crate::liquidity::LiquidityLock;
+
+#[derive(Accounts)]
+pub struct FinalizePresale<'info> {
+    #[account(mut, has_one = creator, has_one = token_vault)]
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, presale.key().as_ref(), authority.key().as_ref()],
+        bump = authority_role.bump,
+        constraint = authority_role.has(Role::Owner) @ PresaleError::Unauthorized,
+    )]
+    pub authority_role: Account<'info, RoleAssignment>,
+    /// CHECK: Creador de la preventa; recibe lo recaudado
+    #[account(mut)]
+    pub creator: UncheckedAccount<'info>,
+    /// CHECK: PDA de la preventa que custodia los pagos y el token_vault
+    #[account(mut, seeds = [presale.key().as_ref()], bump = presale.bump)]
+    pub sale_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub token_vault: InterfaceAccount<'info, TokenAccount>,
+    /// Solo con `UnsoldTokenAction::Return`: recibe los tokens no vendidos
+    #[account(mut)]
+    pub creator_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Mint del token de venta; se quema de él con `UnsoldTokenAction::Burn`
+    #[account(mut, address = presale.sale_token @ PresaleError::InvalidSaleMint)]
+    pub sale_mint: InterfaceAccount<'info, Mint>,
+    /// Solo en modo `RaiseMode::SplToken`: bóveda de recaudación de la preventa
+    #[account(mut)]
+    pub raise_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo en modo `RaiseMode::SplToken`: recibe lo recaudado
+    #[account(mut)]
+    pub creator_raise_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: PDA del protocolo que recibe la comisión sobre lo recaudado
+    #[account(mut, seeds = [FEE_VAULT_SEED], bump)]
+    pub fee_vault: AccountInfo<'info>,
+    /// Solo en modo `RaiseMode::SplToken` con comisión: cuenta de `fee_vault` en el token de recaudación
+    #[account(mut)]
+    pub fee_raise_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo en modo `RaiseMode::SplToken`: mint del token de recaudación
+    pub raise_mint: Option<InterfaceAccount<'info, Mint>>,
+    /// Programa del token de venta (clásico o Token-2022)
+    pub token_program: Interface<'info, TokenInterface>,
+    /// Solo en modo `RaiseMode::SplToken`: programa del token de recaudación
+    pub raise_token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+    /// Solo si la preventa bloquea liquidez: custodia con bloqueo temporal
+    #[account(
+        init,
+        payer = authority,
+        space = LiquidityLock::LEN,
+        seeds = [LiquidityLock::SEED, presale.key().as_ref()],
+        bump,
+    )]
+    pub liquidity_lock: Option<Account<'info, LiquidityLock>>,
+    /// Solo si la preventa bloquea liquidez: cuenta de `liquidity_lock` en el token de venta
+    #[account(mut)]
+    pub lock_sale_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo si la preventa bloquea liquidez en modo `RaiseMode::SplToken`: cuenta de
+    /// `liquidity_lock` en el token de recaudación
+    #[account(mut)]
+    pub lock_raise_account: Option<InterfaceAccount<'info, TokenAccount>>,
+}
+
+pub fn finalize_presale<'info>(ctx: Context<'_, '_, '_, 'info, FinalizePresale<'info>>) -> Result<()> {
+    let presale = &mut ctx.accounts.presale;
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // Se puede liquidar al terminar la ventana o al agotarse los tokens; en modo
+    // overflow siempre hay que esperar al final de la ventana de depósitos
+    require!(presale.status == PresaleStatus::Active, PresaleError::PresaleNotActive);
+    require!(current_time > presale.end_time || presale.is_sold_out(), PresaleError::PresaleNotEnded);
+
+    // Fijar el precio de cierre de la subasta antes de comprobar el soft cap:
+    // sin agotarse, termina en el piso
+    presale.clearing_price = presale.final_clearing_price();
+    require!(presale.soft_cap_reached(), PresaleError::SoftCapNotReached);
+
+    let presale_key = presale.key();
+    let authority_seeds = &[presale_key.as_ref(), &[presale.bump]];
+    let signer = &[&authority_seeds[..]];
+
+    // Liberar lo recaudado al creador, descontando la comisión del protocolo fijada
+    // al crear la preventa. Se calcula sobre lo liquidado y no sobre depósitos que
+    // luego se devuelven. Las recompensas de referidos quedan en custodia hasta
+    // que cada referidor las reclame
+    let settled = presale.settled_proceeds()?;
+    let protocol_fee = raise_fee(settled, presale.raise_fee_bps)?;
+    let referral_rewards = presale.settled_referral_rewards(presale.referral_rewards)?;
+    let net_proceeds = settled
+        .checked_sub(protocol_fee)
+        .and_then(|proceeds| proceeds.checked_sub(referral_rewards))
+        .ok_or(PresaleError::CalculationError)?;
+    // La parte configurada de lo que recibe el creador queda bloqueada como liquidez
+    let locked_proceeds = presale.liquidity_lock.locked_proceeds(net_proceeds)?;
+    let proceeds = net_proceeds - locked_proceeds;
+    pay_from_escrow(
+        presale,
+        &ctx.accounts.sale_authority,
+        &ctx.accounts.fee_vault,
+        ctx.accounts.fee_raise_account.as_ref(),
+        ctx.accounts.raise_vault.as_ref(),
+        ctx.accounts.raise_mint.as_ref(),
+        ctx.accounts.raise_token_program.as_ref(),
+        &ctx.accounts.system_program,
+        ctx.remaining_accounts,
+        protocol_fee,
+    )?;
+    pay_from_escrow(
+        presale,
+        &ctx.accounts.sale_authority,
+        &ctx.accounts.creator.to_account_info(),
+        ctx.accounts.creator_raise_account.as_ref(),
+        ctx.accounts.raise_vault.as_ref(),
+        ctx.accounts.raise_mint.as_ref(),
+        ctx.accounts.raise_token_program.as_ref(),
+        &ctx.accounts.system_program,
+        ctx.remaining_accounts,
+        proceeds,
+    )?;
+
+    // Mover a la custodia con bloqueo temporal la liquidez comprometida al crear la preventa
+    if presale.liquidity_lock.is_enabled() {
+        let liquidity_lock = ctx.accounts.liquidity_lock.as_mut().ok_or(PresaleError::InvalidLiquidityLock)?;
+        let liquidity_lock_info = liquidity_lock.to_account_info();
+        let lock_sale_account = ctx.accounts.lock_sale_account.as_mut().ok_or(PresaleError::InvalidLiquidityLock)?;
+        require!(
+            lock_sale_account.owner == liquidity_lock_info.key() && lock_sale_account.mint == presale.sale_token,
+            PresaleError::InvalidLiquidityLock
+        );
+
+        // Se registra lo recibido: con comisión de transferencia llega menos de lo enviado
+        let sale_balance_before = lock_sale_account.amount;
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.sale_mint,
+            lock_sale_account,
+            &ctx.accounts.sale_authority,
+            ctx.remaining_accounts,
+            presale.liquidity_lock.sale_tokens,
+            signer,
+        )?;
+        lock_sale_account.reload()?;
+        let locked_sale_tokens = lock_sale_account.amount.saturating_sub(sale_balance_before);
+
+        let raise_token_account = match presale.raise_mode {
+            RaiseMode::NativeSol => None,
+            RaiseMode::SplToken => {
+                let lock_raise_account = ctx.accounts.lock_raise_account.as_ref().ok_or(PresaleError::InvalidLiquidityLock)?;
+                require!(
+                    lock_raise_account.owner == liquidity_lock_info.key() && lock_raise_account.mint == presale.raise_token,
+                    PresaleError::InvalidLiquidityLock
+                );
+                Some(lock_raise_account)
+            }
+        };
+        let raise_balance_before = raise_token_account.map(|account| account.amount).unwrap_or(0);
+        pay_from_escrow(
+            presale,
+            &ctx.accounts.sale_authority,
+            &liquidity_lock_info,
+            raise_token_account,
+            ctx.accounts.raise_vault.as_ref(),
+            ctx.accounts.raise_mint.as_ref(),
+            ctx.accounts.raise_token_program.as_ref(),
+            &ctx.accounts.system_program,
+            ctx.remaining_accounts,
+            locked_proceeds,
+        )?;
+        let locked_proceeds = match ctx.accounts.lock_raise_account.as_mut() {
+            Some(lock_raise_account) if presale.raise_mode == RaiseMode::SplToken => {
+                lock_raise_account.reload()?;
+                lock_raise_account.amount.saturating_sub(raise_balance_before)
+            }
+            _ => locked_proceeds,
+        };
+
+        liquidity_lock.presale = presale_key;
+        liquidity_lock.creator = presale.creator;
+        liquidity_lock.unlock_time = current_time
+            .checked_add(presale.liquidity_lock.lock_duration)
+            .ok_or(PresaleError::CalculationError)?;
+        liquidity_lock.sale_token_account = lock_sale_account.key();
+        liquidity_lock.locked_sale_tokens = locked_sale_tokens;
+        liquidity_lock.raise_token_account = ctx.accounts.lock_raise_account
+            .as_ref()
+            .filter(|_| presale.raise_mode == RaiseMode::SplToken)
+            .map(|account| account.key())
+            .unwrap_or_default();
+        liquidity_lock.locked_proceeds = locked_proceeds;
+        liquidity_lock.bump = ctx.bumps.liquidity_lock.ok_or(PresaleError::InvalidLiquidityLock)?;
+
+        emit!(LiquidityLocked {
+            presale: presale_key,
+            creator: presale.creator,
+            locked_sale_tokens,
+            locked_proceeds,
+            unlock_time: liquidity_lock.unlock_time,
+            timestamp: current_time,
+        });
+    } else {
+        require!(ctx.accounts.liquidity_lock.is_none(), PresaleError::InvalidLiquidityLock);
+    }
+
+    // Devolver o quemar los tokens no vendidos según lo elegido al crear la preventa
+    let unsold = presale.total_tokens.saturating_sub(presale.tokens_sold);
+    if unsold > 0 {
+        match presale.unsold_action {
+            UnsoldTokenAction::Return => {
+                let creator_token_account = ctx.accounts.creator_token_account.as_ref().ok_or(PresaleError::InvalidUnsoldTokenAccount)?;
+                require!(
+                    creator_token_account.mint == presale.sale_token
+                        && creator_token_account.owner == presale.creator,
+                    PresaleError::InvalidUnsoldTokenAccount
+                );
+
+                transfer_tokens(
+                    &ctx.accounts.token_program,
+                    &ctx.accounts.token_vault,
+                    &ctx.accounts.sale_mint,
+                    creator_token_account,
+                    &ctx.accounts.sale_authority,
+                    ctx.remaining_accounts,
+                    unsold,
+                    signer,
+                )?;
+            }
+            UnsoldTokenAction::Burn => {
+                let cpi_accounts = Burn {
+                    mint: ctx.accounts.sale_mint.to_account_info(),
+                    from: ctx.accounts.token_vault.to_account_info(),
+                    authority: ctx.accounts.sale_authority.to_account_info(),
+                };
+                token_interface::burn(
+                    CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer),
+                    unsold,
+                )?;
+            }
+        }
+    }
+
+    // A partir de aquí la preventa queda cerrada a nuevas compras
+    presale.status = PresaleStatus::Finalized;
+
+    emit!(PresaleFinalized {
+        presale: presale_key,
+        authority: ctx.accounts.authority.key(),
+        proceeds,
+        protocol_fee,
+        referral_rewards,
+        tokens_sold: presale.tokens_sold,
+        total_raised: presale.total_raised,
+        unsold_tokens: unsold,
+        clearing_price: presale.clearing_price,
+        timestamp: current_time,
+    });
+
+    Ok(())
+}
@@ -1,45 +1,396 @@
 use super::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::escrow::transfer_tokens;
+
+/// Retraso mínimo entre proponer y aplicar un cambio que afecta a los compradores.
+pub const PARAMETER_UPDATE_DELAY: i64 = 24 * 60 * 60;
+
+/// Cambios de parámetros de una preventa; los campos en `None` no se modifican.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct ParameterUpdate {
+    pub price: Option<u64>,
+    pub total_tokens: Option<u64>,
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    pub vesting_end_time: Option<i64>,
+    pub raise_goal: Option<u64>,
+    pub max_entries: Option<u64>,
+}
+
+impl ParameterUpdate {
+    pub const LEN: usize = 7 * (1 + 8);
+
+    /// Cambios que no empeoran las condiciones de los compradores: extender
+    /// `end_time`, salvo en una subasta holandesa, donde mover el fin cambia la
+    /// curva de precio en plena venta, o ampliar `max_entries`. Bajar el tope
+    /// pasa por propuesta y nunca puede quedar por debajo de las entradas ya
+    /// ocupadas.
+    pub fn is_safe_for(&self, sale: &Presale) -> bool {
+        let end_time_safe = match self.end_time {
+            Some(end_time) => end_time >= sale.end_time && sale.dutch_auction.is_none(),
+            None => true,
+        };
+        // 0 significa sin tope, el mayor posible
+        let cap = |max_entries: u64| if max_entries == 0 { u64::MAX } else { max_entries };
+        let max_entries_safe = match self.max_entries {
+            Some(max_entries) => cap(max_entries) >= cap(sale.max_entries),
+            None => true,
+        };
+        self.price.is_none()
+            && self.total_tokens.is_none()
+            && self.start_time.is_none()
+            && self.vesting_end_time.is_none()
+            && self.raise_goal.is_none()
+            && end_time_safe
+            && max_entries_safe
+    }
+
+    pub fn apply(&self, sale: &mut Presale) -> Result<()> {
+        if let Some(price) = self.price {
+            sale.price = price;
+        }
+        if let Some(total_tokens) = self.total_tokens {
+            sale.total_tokens = total_tokens;
+        }
+        if let Some(start_time) = self.start_time {
+            sale.start_time = start_time;
+        }
+        if let Some(end_time) = self.end_time {
+            sale.end_time = end_time;
+        }
+        if let Some(vesting_end_time) = self.vesting_end_time {
+            sale.vesting_end_time = vesting_end_time;
+        }
+        if let Some(raise_goal) = self.raise_goal {
+            sale.raise_goal = raise_goal;
+        }
+        if let Some(max_entries) = self.max_entries {
+            sale.max_entries = max_entries;
+        }
+        sale.validate_parameters()
+    }
+}
+
+#[account]
+pub struct ParameterProposal {
+    pub presale: Pubkey,
+    pub update: ParameterUpdate,
+    pub proposed_at: i64,
+    pub executable_at: i64,
+    pub bump: u8,
+}
+
+impl ParameterProposal {
+    pub const SEED: &'static [u8] = b"proposal";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // presale
+        ParameterUpdate::LEN + // update
+        8 + // proposed_at
+        8 + // executable_at
+        1; // bump
+}
 
 #[derive(Accounts)]
 pub struct UpdateParameters<'info> {
     #[account(mut)]
     pub sale: Account<'info, Presale>,
     pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Owner) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
 }
 
-pub fn update_parameters(ctx: Context<UpdateParameters>, new_price: Option<u64>, new_total_tokens: Option<u64>) -> Result<()> {
+#[derive(Accounts)]
+pub struct ProposeParameterUpdate<'info> {
+    pub sale: Account<'info, Presale>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Owner) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+    #[account(
+        init,
+        payer = admin,
+        space = ParameterProposal::LEN,
+        seeds = [ParameterProposal::SEED, sale.key().as_ref()],
+        bump,
+    )]
+    pub proposal: Account<'info, ParameterProposal>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteParameterUpdate<'info> {
+    #[account(mut, has_one = token_vault)]
+    pub sale: Account<'info, Presale>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Owner) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [ParameterProposal::SEED, sale.key().as_ref()],
+        bump = proposal.bump,
+    )]
+    pub proposal: Account<'info, ParameterProposal>,
+    #[account(mut)]
+    pub token_vault: InterfaceAccount<'info, TokenAccount>,
+    /// CHECK: PDA de la preventa que firma las transferencias del token_vault
+    #[account(seeds = [sale.key().as_ref()], bump = sale.bump)]
+    pub sale_authority: AccountInfo<'info>,
+    /// Solo si la propuesta reduce `total_tokens`: recibe los tokens sobrantes
+    #[account(mut)]
+    pub creator_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = sale.sale_token @ PresaleError::InvalidSaleMint)]
+    pub sale_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CancelParameterUpdate<'info> {
+    pub sale: Account<'info, Presale>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Owner) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [ParameterProposal::SEED, sale.key().as_ref()],
+        bump = proposal.bump,
+    )]
+    pub proposal: Account<'info, ParameterProposal>,
+}
+
+/// Aplica de inmediato los cambios seguros; el resto debe pasar por una propuesta.
+pub fn update_parameters(ctx: Context<UpdateParameters>, update: ParameterUpdate) -> Result<()> {
     let sale = &mut ctx.accounts.sale;
-    if let Some(price) = new_price {
-        sale.price = price;
+    let current_time = Clock::get()?.unix_timestamp;
+
+    if !update.is_safe_for(sale) {
+        require!(current_time < sale.start_time, PresaleError::PresaleAlreadyStarted);
+        return err!(PresaleError::ParameterChangeRequiresProposal);
     }
-    if let Some(total_tokens) = new_total_tokens {
-        sale.total_tokens = total_tokens;
+    // Una venta ya terminada no se reabre: si no alcanzó el soft cap, sus
+    // compradores tienen derecho al reembolso
+    sale.refresh_status(current_time);
+    require!(sale.status == PresaleStatus::Active, PresaleError::PresaleNotActive);
+    require!(current_time <= sale.end_time, PresaleError::PresaleNotActive);
+
+    update.apply(sale)?;
+
+    emit!(ParametersUpdated {
+        presale: sale.key(),
+        authority: ctx.accounts.admin.key(),
+        update,
+        from_proposal: false,
+        returned_tokens: 0,
+        timestamp: current_time,
+    });
+    Ok(())
+}
+
+pub fn propose_parameter_update(ctx: Context<ProposeParameterUpdate>, update: ParameterUpdate) -> Result<()> {
+    let sale = &ctx.accounts.sale;
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // La propuesta debe poder ejecutarse antes de que empiece la venta
+    let executable_at = current_time
+        .checked_add(PARAMETER_UPDATE_DELAY)
+        .ok_or(PresaleError::CalculationError)?;
+    require!(executable_at < sale.start_time, PresaleError::PresaleAlreadyStarted);
+
+    let proposal = &mut ctx.accounts.proposal;
+    proposal.presale = sale.key();
+    proposal.update = update.clone();
+    proposal.proposed_at = current_time;
+    proposal.executable_at = executable_at;
+    proposal.bump = ctx.bumps.proposal;
+
+    emit!(ParameterUpdateProposed {
+        presale: sale.key(),
+        authority: ctx.accounts.admin.key(),
+        update,
+        executable_at,
+        timestamp: current_time,
+    });
+    Ok(())
+}
+
+pub fn execute_parameter_update<'info>(ctx: Context<'_, '_, '_, 'info, ExecuteParameterUpdate<'info>>) -> Result<()> {
+    let sale = &mut ctx.accounts.sale;
+    let proposal = &ctx.accounts.proposal;
+    let current_time = Clock::get()?.unix_timestamp;
+
+    require!(current_time >= proposal.executable_at, PresaleError::TimelockNotExpired);
+    require!(current_time < sale.start_time, PresaleError::PresaleAlreadyStarted);
+
+    let previous_total_tokens = sale.total_tokens;
+    proposal.update.apply(sale)?;
+
+    // Si baja el total a la venta, el sobrante vuelve al creador en lugar de
+    // quedar en el token_vault sin forma de retirarlo
+    let returned_tokens = previous_total_tokens.saturating_sub(sale.total_tokens);
+    if returned_tokens > 0 {
+        let creator_token_account = ctx.accounts.creator_token_account.as_ref().ok_or(PresaleError::InvalidUnsoldTokenAccount)?;
+        require!(
+            creator_token_account.mint == sale.sale_token && creator_token_account.owner == sale.creator,
+            PresaleError::InvalidUnsoldTokenAccount
+        );
+
+        let sale_key = sale.key();
+        let authority_seeds = &[sale_key.as_ref(), &[sale.bump]];
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.token_vault,
+            &ctx.accounts.sale_mint,
+            creator_token_account,
+            &ctx.accounts.sale_authority,
+            ctx.remaining_accounts,
+            returned_tokens,
+            &[&authority_seeds[..]],
+        )?;
+        ctx.accounts.token_vault.reload()?;
     }
+
+    // El token_vault debe cubrir el nuevo total a la venta y los tokens
+    // reservados para el bloqueo de liquidez
+    let required_tokens = sale.total_tokens
+        .checked_add(sale.liquidity_lock.sale_tokens)
+        .ok_or(PresaleError::CalculationError)?;
+    require!(
+        ctx.accounts.token_vault.amount >= required_tokens,
+        PresaleError::InvalidParameters
+    );
+
+    emit!(ParametersUpdated {
+        presale: sale.key(),
+        authority: ctx.accounts.admin.key(),
+        update: proposal.update.clone(),
+        from_proposal: true,
+        returned_tokens,
+        timestamp: current_time,
+    });
+    Ok(())
+}
+
+pub fn cancel_parameter_update(ctx: Context<CancelParameterUpdate>) -> Result<()> {
+    emit!(ParameterUpdateCancelled {
+        presale: ctx.accounts.sale.key(),
+        authority: ctx.accounts.admin.key(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     Ok(())
 }
 
 #[derive(Accounts)]
 pub struct PauseContract<'info> {
-    #[account(mut)]
-    pub sale: Account<'info, Presale>,
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
     pub admin: Signer<'info>,
 }
 
 #[derive(Accounts)]
 pub struct UnpauseContract<'info> {
-    #[account(mut)]
-    pub sale: Account<'info, Presale>,
+    #[account(
+        mut,
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
     pub admin: Signer<'info>,
 }
 
-pub fn pause_contract(ctx: Context<PauseContract>) -> Result<()> {
-    let sale = &mut ctx.accounts.sale;
-    sale.paused = true;
+/// Activa los interruptores indicados para todas las preventas a la vez.
+pub fn pause_contract(ctx: Context<PauseContract>, scope: CircuitBreaker) -> Result<()> {
+    let breaker = &mut ctx.accounts.config.circuit_breaker;
+    breaker.buys_paused |= scope.buys_paused;
+    breaker.claims_paused |= scope.claims_paused;
+    breaker.launches_paused |= scope.launches_paused;
+
+    emit!(ProtocolPauseChanged {
+        admin: ctx.accounts.admin.key(),
+        scope,
+        circuit_breaker: *breaker,
+        paused: true,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     Ok(())
 }
 
-pub fn unpause_contract(ctx: Context<UnpauseContract>) -> Result<()> {
-    let sale = &mut ctx.accounts.sale;
-    sale.paused = false;
+/// Desactiva los interruptores indicados.
+pub fn unpause_contract(ctx: Context<UnpauseContract>, scope: CircuitBreaker) -> Result<()> {
+    let breaker = &mut ctx.accounts.config.circuit_breaker;
+    breaker.buys_paused &= !scope.buys_paused;
+    breaker.claims_paused &= !scope.claims_paused;
+    breaker.launches_paused &= !scope.launches_paused;
+
+    emit!(ProtocolPauseChanged {
+        admin: ctx.accounts.admin.key(),
+        scope,
+        circuit_breaker: *breaker,
+        paused: false,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn max_entries_update(max_entries: u64) -> ParameterUpdate {
+        ParameterUpdate { max_entries: Some(max_entries), ..Default::default() }
+    }
+
+    #[test]
+    fn only_raising_max_entries_is_safe() {
+        let sale = Presale { max_entries: 100, whitelist_size: 40, ..Default::default() };
+        assert!(max_entries_update(150).is_safe_for(&sale));
+        assert!(max_entries_update(0).is_safe_for(&sale));
+        assert!(!max_entries_update(60).is_safe_for(&sale));
+
+        // Sin tope, cualquier tope nuevo lo reduce
+        let sale = Presale { max_entries: 0, ..sale };
+        assert!(!max_entries_update(1_000).is_safe_for(&sale));
+    }
+
+    #[test]
+    fn max_entries_cannot_drop_below_the_entries_in_use() {
+        let mut sale = Presale {
+            price: 1,
+            start_time: 10,
+            end_time: 20,
+            vesting_end_time: 20,
+            max_entries: 100,
+            whitelist_size: 40,
+            allocation_count: 50,
+            ..Default::default()
+        };
+        assert!(max_entries_update(49).apply(&mut sale.clone()).is_err());
+        assert!(max_entries_update(50).apply(&mut sale).is_ok());
+        assert_eq!(sale.max_entries, 50);
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::too_many_arguments)]
+
 use anchor_lang::prelude::*;
 
 declare_id!("74VT9QxrMB8gWYynpS7m9bxAygWy6tTAqHELeDsVmRNV");
@@ -12,6 +14,21 @@ pub mod presale;
 pub mod claim;
 pub mod sale;
 pub mod stats;
+pub mod purchase;
+pub mod refund;
+pub mod finalize;
+pub mod config;
+pub mod roles;
+pub mod escrow;
+pub mod events;
+pub mod fees;
+pub mod referral;
+pub mod phases;
+pub mod staking;
+pub mod lottery;
+pub mod liquidity;
+pub mod team;
+pub mod beneficiary;
 
 use crate::whitelist::*;
 use crate::allocation::*;
@@ -23,8 +40,22 @@ use crate::presale::*;
 use crate::claim::*;
 use crate::sale::*;
 use crate::stats::*;
+use crate::purchase::*;
+use crate::refund::*;
+use crate::finalize::*;
+use crate::config::*;
+use crate::roles::*;
+use crate::events::*;
+use crate::fees::*;
+use crate::referral::*;
+use crate::phases::*;
+use crate::staking::*;
+use crate::lottery::*;
+use crate::liquidity::*;
+use crate::team::*;
+use crate::beneficiary::*;
 
-pub use crate::pricing::{GlobalStats, PresaleInfo};
+pub use crate::pricing::{DutchAuction, GlobalStats, PresaleInfo, PriceTier};
 
 #[program]
 pub mod launchpadinsoon {
@@ -43,10 +74,97 @@ pub mod launchpadinsoon {
         sale::initialize(ctx, total_tokens, price, start_time, end_time, vesting_end_time, raise_goal, bump)
     }
 
+    pub fn initialize_config(ctx: Context<InitializeConfig>) -> Result<()> {
+        config::initialize_config(ctx)
+    }
+
+    pub fn set_config_admin(ctx: Context<SetConfigAdmin>, new_admin: Pubkey) -> Result<()> {
+        config::set_config_admin(ctx, new_admin)
+    }
+
+    pub fn initialize_fee_config(ctx: Context<InitializeFeeConfig>, listing_fee: u64, weekly_fee: u64, raise_fee_bps: u16) -> Result<()> {
+        fees::initialize_fee_config(ctx, listing_fee, weekly_fee, raise_fee_bps)
+    }
+
+    pub fn update_fee_config(ctx: Context<UpdateFeeConfig>, listing_fee: u64, weekly_fee: u64, raise_fee_bps: u16) -> Result<()> {
+        fees::update_fee_config(ctx, listing_fee, weekly_fee, raise_fee_bps)
+    }
+
+    pub fn grant_fee_exemption(ctx: Context<GrantFeeExemption>) -> Result<()> {
+        fees::grant_fee_exemption(ctx)
+    }
+
+    pub fn revoke_fee_exemption(ctx: Context<RevokeFeeExemption>) -> Result<()> {
+        fees::revoke_fee_exemption(ctx)
+    }
+
+    pub fn withdraw_fees<'info>(ctx: Context<'_, '_, '_, 'info, WithdrawFees<'info>>, amount: u64) -> Result<()> {
+        fees::withdraw_fees(ctx, amount)
+    }
+
+    pub fn initialize_stake_pool(ctx: Context<InitializeStakePool>, lock_duration: i64, tiers: Vec<StakingTier>) -> Result<()> {
+        staking::initialize_stake_pool(ctx, lock_duration, tiers)
+    }
+
+    pub fn update_stake_pool(ctx: Context<UpdateStakePool>, lock_duration: i64, tiers: Vec<StakingTier>) -> Result<()> {
+        staking::update_stake_pool(ctx, lock_duration, tiers)
+    }
+
+    pub fn stake<'info>(ctx: Context<'_, '_, '_, 'info, Stake<'info>>, amount: u64) -> Result<()> {
+        staking::stake(ctx, amount)
+    }
+
+    pub fn unstake<'info>(ctx: Context<'_, '_, '_, 'info, Unstake<'info>>, amount: u64) -> Result<()> {
+        staking::unstake(ctx, amount)
+    }
+
+    pub fn grant_role(ctx: Context<GrantRole>, role: Role) -> Result<()> {
+        roles::grant_role(ctx, role)
+    }
+
+    pub fn revoke_role(ctx: Context<RevokeRole>, role: Role) -> Result<()> {
+        roles::revoke_role(ctx, role)
+    }
+
     pub fn add_to_whitelist(ctx: Context<AddToWhitelist>, user: Pubkey) -> Result<()> {
         whitelist::add_to_whitelist(ctx, user)
     }
 
+    pub fn update_whitelist_batch<'info>(ctx: Context<'_, '_, '_, 'info, UpdateWhitelistBatch<'info>>, changes: Vec<WhitelistChange>) -> Result<()> {
+        whitelist::update_whitelist_batch(ctx, changes)
+    }
+
+    pub fn set_merkle_root(ctx: Context<SetMerkleRoot>, merkle_root: [u8; 32], merkle_allocations: bool) -> Result<()> {
+        whitelist::set_merkle_root(ctx, merkle_root, merkle_allocations)
+    }
+
+    pub fn initialize_lottery(
+        ctx: Context<InitializeLottery>,
+        registration_start: i64,
+        registration_end: i64,
+        winner_count: u32,
+        allocation_per_winner: u64,
+        commitment: [u8; 32],
+    ) -> Result<()> {
+        lottery::initialize_lottery(ctx, registration_start, registration_end, winner_count, allocation_per_winner, commitment)
+    }
+
+    pub fn register_for_lottery(ctx: Context<RegisterForLottery>) -> Result<()> {
+        lottery::register_for_lottery(ctx)
+    }
+
+    pub fn close_lottery_registration(ctx: Context<CloseLotteryRegistration>) -> Result<()> {
+        lottery::close_lottery_registration(ctx)
+    }
+
+    pub fn draw_lottery(ctx: Context<DrawLottery>, secret: [u8; 32]) -> Result<()> {
+        lottery::draw_lottery(ctx, secret)
+    }
+
+    pub fn expire_lottery_draw(ctx: Context<ExpireLotteryDraw>) -> Result<()> {
+        lottery::expire_lottery_draw(ctx)
+    }
+
     pub fn pause_sale(ctx: Context<PauseSale>) -> Result<()> {
         security::pause_sale(ctx)
     }
@@ -59,28 +177,82 @@ pub mod launchpadinsoon {
         allocation::set_allocation(ctx, user, allocation)
     }
 
-    pub fn buy_tokens(ctx: Context<BuyTokens>, presale_id: u64, amount: u64) -> Result<()> {
-        pricing::buy_tokens(ctx, presale_id, amount)
+    pub fn set_purchase_limits(ctx: Context<SetPurchaseLimits>, purchase_limits: PurchaseLimits) -> Result<()> {
+        allocation::set_purchase_limits(ctx, purchase_limits)
+    }
+
+    pub fn buy_tokens<'info>(
+        ctx: Context<'_, '_, '_, 'info, BuyTokens<'info>>,
+        presale_id: u64,
+        amount: u64,
+        merkle_proof: Vec<[u8; 32]>,
+        merkle_allocation: Option<u64>,
+    ) -> Result<()> {
+        pricing::buy_tokens(ctx, presale_id, amount, merkle_proof, merkle_allocation)
     }
 
     pub fn create_vesting(ctx: Context<CreateVesting>, amount: u64, release_time: i64) -> Result<()> {
         vesting::create_vesting(ctx, amount, release_time)
     }
 
-    pub fn claim_tokens(ctx: Context<ClaimTokens>) -> Result<()> {
+    pub fn claim_tokens<'info>(ctx: Context<'_, '_, '_, 'info, ClaimTokens<'info>>) -> Result<()> {
         claim::claim_tokens(ctx)
     }
 
-    pub fn pause_contract(ctx: Context<PauseContract>) -> Result<()> {
-        governance::pause_contract(ctx)
+    pub fn refund<'info>(ctx: Context<'_, '_, '_, 'info, Refund<'info>>) -> Result<()> {
+        refund::refund(ctx)
+    }
+
+    pub fn reclaim_sale_tokens<'info>(ctx: Context<'_, '_, '_, 'info, ReclaimSaleTokens<'info>>) -> Result<()> {
+        refund::reclaim_sale_tokens(ctx)
+    }
+
+    pub fn register_referrer(ctx: Context<RegisterReferrer>) -> Result<()> {
+        referral::register_referrer(ctx)
+    }
+
+    pub fn claim_referral_rewards<'info>(ctx: Context<'_, '_, '_, 'info, ClaimReferralRewards<'info>>) -> Result<()> {
+        referral::claim_referral_rewards(ctx)
+    }
+
+    pub fn finalize_presale<'info>(ctx: Context<'_, '_, '_, 'info, FinalizePresale<'info>>) -> Result<()> {
+        finalize::finalize_presale(ctx)
+    }
+
+    pub fn deposit_lp_tokens<'info>(ctx: Context<'_, '_, '_, 'info, DepositLpTokens<'info>>, amount: u64) -> Result<()> {
+        liquidity::deposit_lp_tokens(ctx, amount)
+    }
+
+    pub fn withdraw_liquidity<'info>(ctx: Context<'_, '_, '_, 'info, WithdrawLiquidity<'info>>, asset: LockedAsset) -> Result<()> {
+        liquidity::withdraw_liquidity(ctx, asset)
+    }
+
+    pub fn update_parameters(ctx: Context<UpdateParameters>, update: ParameterUpdate) -> Result<()> {
+        governance::update_parameters(ctx, update)
+    }
+
+    pub fn propose_parameter_update(ctx: Context<ProposeParameterUpdate>, update: ParameterUpdate) -> Result<()> {
+        governance::propose_parameter_update(ctx, update)
+    }
+
+    pub fn execute_parameter_update<'info>(ctx: Context<'_, '_, '_, 'info, ExecuteParameterUpdate<'info>>) -> Result<()> {
+        governance::execute_parameter_update(ctx)
+    }
+
+    pub fn cancel_parameter_update(ctx: Context<CancelParameterUpdate>) -> Result<()> {
+        governance::cancel_parameter_update(ctx)
     }
 
-    pub fn unpause_contract(ctx: Context<UnpauseContract>) -> Result<()> {
-        governance::unpause_contract(ctx)
+    pub fn pause_contract(ctx: Context<PauseContract>, scope: CircuitBreaker) -> Result<()> {
+        governance::pause_contract(ctx, scope)
     }
 
-    pub fn initialize_presale(
-        ctx: Context<InitializePresale>,
+    pub fn unpause_contract(ctx: Context<UnpauseContract>, scope: CircuitBreaker) -> Result<()> {
+        governance::unpause_contract(ctx, scope)
+    }
+
+    pub fn initialize_presale<'info>(
+        ctx: Context<'_, '_, '_, 'info, InitializePresale<'info>>,
         id: u64,
         total_tokens: u64,
         price: u64,
@@ -90,8 +262,61 @@ pub mod launchpadinsoon {
         raise_goal: u64,
         bump: u8,
         max_entries: u64,
+        vesting_schedule: VestingSchedule,
+        raise_mode: RaiseMode,
+        unsold_action: UnsoldTokenAction,
+        price_tiers: Vec<PriceTier>,
+        dutch_auction: Option<DutchAuction>,
+        sale_mode: SaleMode,
+        referral_bps: u16,
+        purchase_limits: PurchaseLimits,
+        phases: Vec<SalePhase>,
+        tokens_per_tier_weight: u64,
+        liquidity_lock: LiquidityLockConfig,
+        transfer_requires_approval: bool,
     ) -> Result<()> {
-        presale::initialize_presale(ctx, id, total_tokens, price, start_time, end_time, vesting_end_time, raise_goal, bump, max_entries)
+        presale::initialize_presale(ctx, id, total_tokens, price, start_time, end_time, vesting_end_time, raise_goal, bump, max_entries, vesting_schedule, raise_mode, unsold_action, price_tiers, dutch_auction, sale_mode, referral_bps, purchase_limits, phases, tokens_per_tier_weight, liquidity_lock, transfer_requires_approval)
+    }
+
+    pub fn propose_beneficiary_transfer(ctx: Context<ProposeBeneficiaryTransfer>, new_beneficiary: Pubkey) -> Result<()> {
+        beneficiary::propose_beneficiary_transfer(ctx, new_beneficiary)
+    }
+
+    pub fn approve_beneficiary_transfer(ctx: Context<ApproveBeneficiaryTransfer>) -> Result<()> {
+        beneficiary::approve_beneficiary_transfer(ctx)
+    }
+
+    pub fn cancel_beneficiary_transfer(ctx: Context<CancelBeneficiaryTransfer>) -> Result<()> {
+        beneficiary::cancel_beneficiary_transfer(ctx)
+    }
+
+    pub fn accept_beneficiary_transfer(ctx: Context<AcceptBeneficiaryTransfer>) -> Result<()> {
+        beneficiary::accept_beneficiary_transfer(ctx)
+    }
+
+    pub fn create_team_grant<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTeamGrant<'info>>,
+        beneficiary: Pubkey,
+        category: GrantCategory,
+        amount: u64,
+        start_time: i64,
+        end_time: i64,
+        schedule: VestingSchedule,
+        revocable: bool,
+    ) -> Result<()> {
+        team::create_team_grant(ctx, beneficiary, category, amount, start_time, end_time, schedule, revocable)
+    }
+
+    pub fn revoke_team_grant<'info>(ctx: Context<'_, '_, '_, 'info, RevokeTeamGrant<'info>>) -> Result<()> {
+        team::revoke_team_grant(ctx)
+    }
+
+    pub fn claim_team_grant<'info>(ctx: Context<'_, '_, '_, 'info, ClaimTeamGrant<'info>>) -> Result<()> {
+        team::claim_team_grant(ctx)
+    }
+
+    pub fn get_team_grant_stats(ctx: Context<GetTeamGrantStats>) -> Result<TeamGrantStats> {
+        stats::get_team_grant_stats(ctx)
     }
 
     pub fn get_presale_stats(ctx: Context<GetPresaleStats>) -> Result<PresaleStats> {
@@ -149,4 +374,108 @@ pub enum PresaleError {
     NoPurchaseFound,
     #[msg("No se encontró información de vesting para este usuario.")]
     NoVestingFound,
+    #[msg("El monto de vesting excede los tokens comprados.")]
+    VestingExceedsPurchase,
+    #[msg("La prueba Merkle no es válida para este comprador.")]
+    InvalidMerkleProof,
+    #[msg("La preventa ya ha comenzado.")]
+    PresaleAlreadyStarted,
+    #[msg("El calendario de vesting no es válido.")]
+    InvalidVestingSchedule,
+    #[msg("La autoridad de la preventa no es válida.")]
+    InvalidSaleAuthority,
+    #[msg("El token de recaudación no es válido.")]
+    InvalidRaiseToken,
+    #[msg("La bóveda de recaudación no es válida.")]
+    InvalidRaiseVault,
+    #[msg("El reembolso solo está disponible si la preventa no alcanzó su meta.")]
+    RefundNotAvailable,
+    #[msg("La preventa no alcanzó su meta de recaudación.")]
+    SoftCapNotReached,
+    #[msg("La preventa aún no ha terminado.")]
+    PresaleNotEnded,
+    #[msg("La cuenta para los tokens no vendidos no es válida.")]
+    InvalidUnsoldTokenAccount,
+    #[msg("No tienes permisos para esta operación.")]
+    Unauthorized,
+    #[msg("Un owner no puede revocar su propio rol de owner.")]
+    CannotRevokeOwnOwnership,
+    #[msg("Los parámetros de la preventa están fuera de los límites permitidos.")]
+    InvalidParameters,
+    #[msg("Este cambio afecta a los compradores y debe pasar por una propuesta.")]
+    ParameterChangeRequiresProposal,
+    #[msg("La propuesta aún está en su período de espera.")]
+    TimelockNotExpired,
+    #[msg("Esta operación está pausada en todo el protocolo.")]
+    ProtocolPaused,
+    #[msg("No quedan suficientes tokens a la venta.")]
+    InsufficientSupply,
+    #[msg("La tabla de tramos de precio no es válida.")]
+    InvalidPriceTiers,
+    #[msg("La configuración de la subasta holandesa no es válida.")]
+    InvalidDutchAuction,
+    #[msg("El depósito excede el límite por wallet.")]
+    DepositCapExceeded,
+    #[msg("La asignación final aún no se conoce.")]
+    AllocationNotFinal,
+    #[msg("El mint del token de venta no coincide con el de la bóveda.")]
+    InvalidSaleMint,
+    #[msg("La bóveda no recibió el monto esperado tras la comisión de transferencia.")]
+    TransferAmountMismatch,
+    #[msg("La configuración de comisiones no es válida.")]
+    InvalidFeeConfig,
+    #[msg("La bóveda de comisiones no tiene saldo suficiente.")]
+    InsufficientFeeBalance,
+    #[msg("Esta preventa no tiene programa de referidos.")]
+    ReferralsDisabled,
+    #[msg("El referidor no es válido para esta compra.")]
+    InvalidReferrer,
+    #[msg("La compra está por debajo del mínimo permitido.")]
+    BelowMinimumPurchase,
+    #[msg("La compra excede el máximo permitido por transacción.")]
+    PurchaseLimitExceeded,
+    #[msg("Las fases de la preventa no son válidas.")]
+    InvalidPhases,
+    #[msg("La compra excede el tope de la fase actual.")]
+    PhaseCapExceeded,
+    #[msg("Los niveles de staking no son válidos.")]
+    InvalidStakingTiers,
+    #[msg("La cuenta no corresponde al pool de staking.")]
+    InvalidStakePool,
+    #[msg("No tienes suficientes tokens en stake.")]
+    InsufficientStake,
+    #[msg("Los tokens en stake siguen bloqueados.")]
+    StakeLocked,
+    #[msg("La configuración del sorteo no es válida.")]
+    InvalidLottery,
+    #[msg("El registro del sorteo no está abierto.")]
+    LotteryRegistrationClosed,
+    #[msg("El sorteo aún no puede realizarse o el slot del sorteo ya expiró.")]
+    LotteryNotReady,
+    #[msg("El secreto revelado no coincide con el compromiso del sorteo.")]
+    InvalidLotteryReveal,
+    #[msg("El sorteo ya se realizó.")]
+    LotteryAlreadyDrawn,
+    #[msg("La configuración o las cuentas del bloqueo de liquidez no son válidas.")]
+    InvalidLiquidityLock,
+    #[msg("La liquidez sigue bloqueada.")]
+    LiquidityLocked,
+    #[msg("La asignación de equipo no es válida.")]
+    InvalidTeamGrant,
+    #[msg("Esta asignación no es revocable.")]
+    GrantNotRevocable,
+    #[msg("La asignación ya fue revocada.")]
+    GrantAlreadyRevoked,
+    #[msg("El nuevo beneficiario no es válido.")]
+    InvalidBeneficiary,
+    #[msg("El traspaso requiere la aprobación de un operador.")]
+    TransferNotApproved,
+    #[msg("El lote de cambios de la lista blanca no es válido.")]
+    InvalidWhitelistBatch,
+    #[msg("No se puede quitar a un usuario que ya compró.")]
+    UserHasPurchased,
+    #[msg("La asignación no puede quedar por debajo de lo ya comprado.")]
+    AllocationBelowPurchased,
+    #[msg("Venció el plazo para revelar el secreto del sorteo.")]
+    LotteryDrawExpired,
 }
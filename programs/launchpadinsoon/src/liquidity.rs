@@ -0,0 +1,243 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{Presale, PresaleError, PresaleStatus, RaiseMode};
+use crate::escrow::{deposit_tokens, transfer_tokens};
+use crate::events::{LiquidityWithdrawn, LpTokensLocked};
+use crate::vesting::BPS_DENOMINATOR;
+
+/// Liquidez que la preventa bloquea al liquidarse. Todo en 0 desactiva el bloqueo.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LiquidityLockConfig {
+    /// Parte de lo que recibe el creador que queda bloqueada, en puntos básicos.
+    pub proceeds_bps: u16,
+    /// Tokens de venta adicionales que el creador deposita al crear la preventa
+    /// y que se bloquean junto con lo recaudado.
+    pub sale_tokens: u64,
+    /// Segundos desde la liquidación hasta que el creador puede retirar.
+    pub lock_duration: i64,
+}
+
+impl LiquidityLockConfig {
+    pub const LEN: usize = 2 + 8 + 8;
+
+    pub fn is_enabled(&self) -> bool {
+        self.proceeds_bps > 0 || self.sale_tokens > 0
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.proceeds_bps as u64 <= BPS_DENOMINATOR
+                && self.lock_duration >= 0
+                && (!self.is_enabled() || self.lock_duration > 0),
+            PresaleError::InvalidLiquidityLock
+        );
+        Ok(())
+    }
+
+    /// Parte de `proceeds` que va al bloqueo.
+    pub fn locked_proceeds(&self, proceeds: u64) -> Result<u64> {
+        let locked = (proceeds as u128)
+            .checked_mul(self.proceeds_bps as u128)
+            .ok_or(PresaleError::CalculationError)?
+            / BPS_DENOMINATOR as u128;
+        Ok(locked as u64)
+    }
+}
+
+/// Activo retirable de un bloqueo de liquidez.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LockedAsset {
+    SaleTokens,
+    /// Lo recaudado: lamports del propio PDA o tokens de recaudación.
+    Proceeds,
+    LpTokens,
+}
+
+/// Custodia con bloqueo temporal creada al liquidar la preventa. La PDA es la
+/// dueña de las cuentas de token bloqueadas y, en modo SOL, guarda los lamports.
+#[account]
+#[derive(Default)]
+pub struct LiquidityLock {
+    pub presale: Pubkey,
+    pub creator: Pubkey,
+    pub unlock_time: i64,
+    pub sale_token_account: Pubkey,
+    pub locked_sale_tokens: u64,
+    /// `Pubkey::default()` en modo SOL.
+    pub raise_token_account: Pubkey,
+    pub locked_proceeds: u64,
+    /// Se fija con el primer depósito de LP tokens.
+    pub lp_mint: Pubkey,
+    pub lp_token_account: Pubkey,
+    pub locked_lp_tokens: u64,
+    pub bump: u8,
+}
+
+impl LiquidityLock {
+    pub const SEED: &'static [u8] = b"liquidity_lock";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // presale
+        32 + // creator
+        8 + // unlock_time
+        32 + // sale_token_account
+        8 + // locked_sale_tokens
+        32 + // raise_token_account
+        8 + // locked_proceeds
+        32 + // lp_mint
+        32 + // lp_token_account
+        8 + // locked_lp_tokens
+        1; // bump
+}
+
+#[derive(Accounts)]
+pub struct DepositLpTokens<'info> {
+    #[account(has_one = creator)]
+    pub presale: Account<'info, Presale>,
+    pub creator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [LiquidityLock::SEED, presale.key().as_ref()],
+        bump = liquidity_lock.bump,
+    )]
+    pub liquidity_lock: Account<'info, LiquidityLock>,
+    #[account(mut)]
+    pub creator_lp_account: InterfaceAccount<'info, TokenAccount>,
+    /// Cuenta de LP tokens cuyo dueño es `liquidity_lock`
+    #[account(mut)]
+    pub lock_lp_account: InterfaceAccount<'info, TokenAccount>,
+    pub lp_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawLiquidity<'info> {
+    #[account(has_one = creator)]
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [LiquidityLock::SEED, presale.key().as_ref()],
+        bump = liquidity_lock.bump,
+    )]
+    pub liquidity_lock: Account<'info, LiquidityLock>,
+    /// Salvo al retirar SOL: cuenta del bloqueo con el activo
+    #[account(mut)]
+    pub lock_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Salvo al retirar SOL: cuenta del creador que recibe el activo
+    #[account(mut)]
+    pub creator_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+}
+
+/// Bloquea LP tokens obtenidos al crear el pool de liquidez, hasta el mismo
+/// `unlock_time` que el resto del bloqueo.
+pub fn deposit_lp_tokens<'info>(ctx: Context<'_, '_, '_, 'info, DepositLpTokens<'info>>, amount: u64) -> Result<()> {
+    require!(ctx.accounts.presale.status == PresaleStatus::Finalized, PresaleError::PresaleNotEnded);
+    let liquidity_lock_key = ctx.accounts.liquidity_lock.key();
+    let liquidity_lock = &ctx.accounts.liquidity_lock;
+    if liquidity_lock.lp_mint == Pubkey::default() {
+        require!(
+            ctx.accounts.lock_lp_account.owner == liquidity_lock_key
+                && ctx.accounts.lock_lp_account.mint == ctx.accounts.lp_mint.key(),
+            PresaleError::InvalidLiquidityLock
+        );
+    } else {
+        require!(
+            ctx.accounts.lp_mint.key() == liquidity_lock.lp_mint
+                && ctx.accounts.lock_lp_account.key() == liquidity_lock.lp_token_account,
+            PresaleError::InvalidLiquidityLock
+        );
+    }
+
+    deposit_tokens(
+        &ctx.accounts.token_program,
+        &ctx.accounts.creator_lp_account,
+        &ctx.accounts.lp_mint,
+        &mut ctx.accounts.lock_lp_account,
+        &ctx.accounts.creator.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+    )?;
+
+    let liquidity_lock = &mut ctx.accounts.liquidity_lock;
+    liquidity_lock.lp_mint = ctx.accounts.lp_mint.key();
+    liquidity_lock.lp_token_account = ctx.accounts.lock_lp_account.key();
+    liquidity_lock.locked_lp_tokens = liquidity_lock.locked_lp_tokens
+        .checked_add(amount)
+        .ok_or(PresaleError::CalculationError)?;
+
+    emit!(LpTokensLocked {
+        presale: liquidity_lock.presale,
+        lp_mint: liquidity_lock.lp_mint,
+        amount,
+        locked_lp_tokens: liquidity_lock.locked_lp_tokens,
+        unlock_time: liquidity_lock.unlock_time,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Entrega al creador todo lo bloqueado de `asset` una vez vencido el bloqueo.
+pub fn withdraw_liquidity<'info>(ctx: Context<'_, '_, '_, 'info, WithdrawLiquidity<'info>>, asset: LockedAsset) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    let liquidity_lock = &ctx.accounts.liquidity_lock;
+    require!(current_time >= liquidity_lock.unlock_time, PresaleError::LiquidityLocked);
+
+    let (lock_account, amount) = match asset {
+        LockedAsset::SaleTokens => (Some(liquidity_lock.sale_token_account), liquidity_lock.locked_sale_tokens),
+        LockedAsset::Proceeds => match ctx.accounts.presale.raise_mode {
+            RaiseMode::NativeSol => (None, liquidity_lock.locked_proceeds),
+            RaiseMode::SplToken => (Some(liquidity_lock.raise_token_account), liquidity_lock.locked_proceeds),
+        },
+        LockedAsset::LpTokens => (Some(liquidity_lock.lp_token_account), liquidity_lock.locked_lp_tokens),
+    };
+    require!(amount > 0, PresaleError::NoTokensToClaim);
+
+    let presale_key = ctx.accounts.presale.key();
+    let lock_seeds = &[LiquidityLock::SEED, presale_key.as_ref(), &[liquidity_lock.bump]];
+    match lock_account {
+        Some(lock_account) => {
+            let lock_token_account = ctx.accounts.lock_token_account.as_ref().ok_or(PresaleError::InvalidLiquidityLock)?;
+            let creator_token_account = ctx.accounts.creator_token_account.as_ref().ok_or(PresaleError::InvalidLiquidityLock)?;
+            let mint = ctx.accounts.mint.as_ref().ok_or(PresaleError::InvalidLiquidityLock)?;
+            let token_program = ctx.accounts.token_program.as_ref().ok_or(PresaleError::InvalidLiquidityLock)?;
+            require!(lock_token_account.key() == lock_account, PresaleError::InvalidLiquidityLock);
+            require!(creator_token_account.owner == ctx.accounts.creator.key(), PresaleError::InvalidLiquidityLock);
+
+            transfer_tokens(
+                token_program,
+                lock_token_account,
+                mint,
+                creator_token_account,
+                &liquidity_lock.to_account_info(),
+                ctx.remaining_accounts,
+                amount,
+                &[&lock_seeds[..]],
+            )?;
+        }
+        None => {
+            // La PDA es del programa: los lamports se mueven directamente
+            **liquidity_lock.to_account_info().try_borrow_mut_lamports()? -= amount;
+            **ctx.accounts.creator.to_account_info().try_borrow_mut_lamports()? += amount;
+        }
+    }
+
+    let liquidity_lock = &mut ctx.accounts.liquidity_lock;
+    match asset {
+        LockedAsset::SaleTokens => liquidity_lock.locked_sale_tokens = 0,
+        LockedAsset::Proceeds => liquidity_lock.locked_proceeds = 0,
+        LockedAsset::LpTokens => liquidity_lock.locked_lp_tokens = 0,
+    }
+
+    emit!(LiquidityWithdrawn {
+        presale: presale_key,
+        creator: liquidity_lock.creator,
+        asset,
+        amount,
+        timestamp: current_time,
+    });
+    Ok(())
+}
@@ -0,0 +1,487 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::sysvar::slot_hashes;
+use crate::{Presale, PresaleError, PresaleStatus, Role, RoleAssignment};
+use crate::events::{LotteryCreated, LotteryDrawExpired, LotteryDrawn, LotteryRegistered, LotteryRegistrationClosed};
+
+/// Slots entre el cierre del registro y el slot cuyo hash entra en el sorteo.
+pub const DRAW_SLOT_DELAY: u64 = 4;
+
+/// Slots desde `draw_slot` en los que el creador puede revelar el secreto. No
+/// supera las 512 entradas del sysvar SlotHashes, así que el hash sigue ahí.
+pub const DRAW_REVEAL_WINDOW: u64 = 512;
+
+/// Tamaño de cada entrada del sysvar SlotHashes: slot (u64) + hash.
+const SLOT_HASH_ENTRY_LEN: usize = 8 + 32;
+
+/// Sorteo de asignaciones de una preventa por commit-reveal.
+///
+/// El creador publica `commitment = keccak(secreto)` al crear el sorteo. Al
+/// cerrar el registro se fija `draw_slot`, un slot futuro; el sorteo se hace
+/// revelando el secreto, y la semilla es `keccak(secreto || hash(draw_slot) || preventa)`.
+/// El creador no conoce el hash del slot al comprometerse y los validadores no
+/// conocen el secreto, así que ninguno puede elegir el resultado.
+///
+/// Los ganadores salen de la permutación `posición = (stride * índice + offset) mod registrados`
+/// sobre los índices de registro: gana el boleto cuya posición es menor que
+/// `winner_count`. Cualquiera puede reproducirla con los datos de esta cuenta.
+///
+/// Si el secreto no se revela dentro de `DRAW_REVEAL_WINDOW`, cualquiera puede
+/// vencer el sorteo: todos los registrados ganan y se reparten a prorrata la
+/// asignación total. Así retener la revelación no le sirve al creador para
+/// elegir otro resultado, y la asignación del sorteo nunca se pierde.
+#[account]
+#[derive(Default)]
+pub struct Lottery {
+    pub presale: Pubkey,
+    pub registration_start: i64,
+    pub registration_end: i64,
+    pub winner_count: u32,
+    pub allocation_per_winner: u64,
+    pub commitment: [u8; 32],
+    pub registrants: u32,
+    /// Slot cuyo hash se usa en el sorteo; 0 mientras el registro siga abierto.
+    pub draw_slot: u64,
+    pub seed: [u8; 32],
+    pub stride: u64,
+    pub offset: u64,
+    pub drawn: bool,
+    /// El sorteo venció sin revelarse y se reparte entre todos los registrados.
+    pub fallback: bool,
+    pub bump: u8,
+}
+
+impl Lottery {
+    pub const SEED: &'static [u8] = b"lottery";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // presale
+        8 + // registration_start
+        8 + // registration_end
+        4 + // winner_count
+        8 + // allocation_per_winner
+        32 + // commitment
+        4 + // registrants
+        8 + // draw_slot
+        32 + // seed
+        8 + // stride
+        8 + // offset
+        1 + // drawn
+        1 + // fallback
+        1; // bump
+
+    pub fn is_registration_open(&self, current_time: i64) -> bool {
+        self.draw_slot == 0
+            && current_time >= self.registration_start
+            && current_time <= self.registration_end
+    }
+
+    /// Si el boleto `ticket_index` ganó; siempre `false` antes del sorteo.
+    pub fn is_winner(&self, ticket_index: u32) -> bool {
+        if !self.drawn || ticket_index >= self.registrants {
+            return false;
+        }
+        if self.fallback {
+            return true;
+        }
+        let position = (self.stride as u128 * ticket_index as u128 + self.offset as u128) % self.registrants as u128;
+        position < self.winner_count as u128
+    }
+
+    /// Asignación que obtiene el titular de `ticket`, si ganó.
+    pub fn allocation_for(&self, ticket: &LotteryTicket) -> Option<u64> {
+        let allocation = if self.fallback { self.fallback_allocation() } else { self.allocation_per_winner };
+        self.is_winner(ticket.index).then_some(allocation)
+    }
+
+    /// Parte de cada registrado cuando el sorteo venció: la asignación de
+    /// `winner_count` ganadores repartida entre todos, redondeando hacia abajo.
+    pub fn fallback_allocation(&self) -> u64 {
+        if self.registrants <= self.winner_count {
+            return self.allocation_per_winner;
+        }
+        let total = self.allocation_per_winner as u128 * self.winner_count as u128;
+        (total / self.registrants as u128) as u64
+    }
+}
+
+/// Parámetros de la permutación a partir de la semilla: `stride` coprimo con
+/// `registrants`, para que la permutación sea una biyección.
+pub fn lottery_permutation(seed: &[u8; 32], registrants: u32) -> (u64, u64) {
+    let registrants = registrants.max(1) as u64;
+    let mut stride = u64::from_le_bytes(seed[..8].try_into().unwrap()) % registrants;
+    let offset = u64::from_le_bytes(seed[8..16].try_into().unwrap()) % registrants;
+    if stride == 0 {
+        stride = 1;
+    }
+    while gcd(stride, registrants) != 1 {
+        stride = stride % registrants + 1;
+    }
+    (stride, offset)
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Hash del primer bloque producido en `target_slot` o después, tomado del
+/// sysvar SlotHashes (ordenado del slot más reciente al más antiguo). Falla si
+/// el slot aún no llegó o si ya salió de la ventana que guarda el sysvar.
+fn slot_hash_at(slot_hashes: &AccountInfo, target_slot: u64) -> Result<[u8; 32]> {
+    let data = slot_hashes.try_borrow_data()?;
+    let entries = data.get(..8)
+        .map(|len| u64::from_le_bytes(len.try_into().unwrap()) as usize)
+        .ok_or(PresaleError::LotteryNotReady)?;
+
+    let mut found: Option<(u64, [u8; 32])> = None;
+    for index in 0..entries {
+        let offset = 8 + index * SLOT_HASH_ENTRY_LEN;
+        let Some(entry) = data.get(offset..offset + SLOT_HASH_ENTRY_LEN) else {
+            break;
+        };
+        let slot = u64::from_le_bytes(entry[..8].try_into().unwrap());
+        if slot < target_slot {
+            // La entrada encontrada es la primera en o después de `target_slot`
+            return found.map(|(_, hash)| hash).ok_or_else(|| error!(PresaleError::LotteryNotReady));
+        }
+        found = Some((slot, entry[8..].try_into().unwrap()));
+    }
+    // Sin una entrada anterior no se puede saber si hubo bloques entre
+    // `target_slot` y la más antigua, salvo que coincida exactamente
+    match found {
+        Some((slot, hash)) if slot == target_slot => Ok(hash),
+        _ => err!(PresaleError::LotteryNotReady),
+    }
+}
+
+/// Registro de una wallet en el sorteo; `index` es su número de boleto.
+#[account]
+#[derive(Default)]
+pub struct LotteryTicket {
+    pub presale: Pubkey,
+    pub user: Pubkey,
+    pub index: u32,
+    pub bump: u8,
+}
+
+impl LotteryTicket {
+    pub const SEED: &'static [u8] = b"lottery_ticket";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // presale
+        32 + // user
+        4 + // index
+        1; // bump
+}
+
+#[derive(Accounts)]
+pub struct InitializeLottery<'info> {
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, presale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::WhitelistManager) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+    #[account(
+        init,
+        payer = admin,
+        space = Lottery::LEN,
+        seeds = [Lottery::SEED, presale.key().as_ref()],
+        bump,
+    )]
+    pub lottery: Account<'info, Lottery>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterForLottery<'info> {
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [Lottery::SEED, presale.key().as_ref()],
+        bump = lottery.bump,
+    )]
+    pub lottery: Account<'info, Lottery>,
+    #[account(
+        init,
+        payer = user,
+        space = LotteryTicket::LEN,
+        seeds = [LotteryTicket::SEED, presale.key().as_ref(), user.key().as_ref()],
+        bump,
+    )]
+    pub lottery_ticket: Account<'info, LotteryTicket>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseLotteryRegistration<'info> {
+    pub presale: Account<'info, Presale>,
+    #[account(
+        mut,
+        seeds = [Lottery::SEED, presale.key().as_ref()],
+        bump = lottery.bump,
+    )]
+    pub lottery: Account<'info, Lottery>,
+}
+
+#[derive(Accounts)]
+pub struct DrawLottery<'info> {
+    pub presale: Account<'info, Presale>,
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, presale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::WhitelistManager) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+    #[account(
+        mut,
+        seeds = [Lottery::SEED, presale.key().as_ref()],
+        bump = lottery.bump,
+    )]
+    pub lottery: Account<'info, Lottery>,
+    /// CHECK: Sysvar SlotHashes; se lee a mano porque es demasiado grande para deserializarlo
+    #[account(address = slot_hashes::ID)]
+    pub slot_hashes: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireLotteryDraw<'info> {
+    pub presale: Account<'info, Presale>,
+    #[account(
+        mut,
+        seeds = [Lottery::SEED, presale.key().as_ref()],
+        bump = lottery.bump,
+    )]
+    pub lottery: Account<'info, Lottery>,
+}
+
+pub fn initialize_lottery(
+    ctx: Context<InitializeLottery>,
+    registration_start: i64,
+    registration_end: i64,
+    winner_count: u32,
+    allocation_per_winner: u64,
+    commitment: [u8; 32],
+) -> Result<()> {
+    let presale = &ctx.accounts.presale;
+    let current_time = Clock::get()?.unix_timestamp;
+    require!(presale.status == PresaleStatus::Active, PresaleError::PresaleNotActive);
+    require!(
+        registration_start < registration_end
+            && registration_end > current_time
+            && registration_end < presale.end_time
+            && winner_count > 0
+            && allocation_per_winner > 0
+            && commitment != [0u8; 32],
+        PresaleError::InvalidLottery
+    );
+
+    let lottery = &mut ctx.accounts.lottery;
+    lottery.presale = presale.key();
+    lottery.registration_start = registration_start;
+    lottery.registration_end = registration_end;
+    lottery.winner_count = winner_count;
+    lottery.allocation_per_winner = allocation_per_winner;
+    lottery.commitment = commitment;
+    lottery.bump = ctx.bumps.lottery;
+
+    emit!(LotteryCreated {
+        presale: lottery.presale,
+        registration_start,
+        registration_end,
+        winner_count,
+        allocation_per_winner,
+        commitment,
+        timestamp: current_time,
+    });
+    Ok(())
+}
+
+pub fn register_for_lottery(ctx: Context<RegisterForLottery>) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    let lottery = &mut ctx.accounts.lottery;
+    require!(lottery.is_registration_open(current_time), PresaleError::LotteryRegistrationClosed);
+
+    let lottery_ticket = &mut ctx.accounts.lottery_ticket;
+    lottery_ticket.presale = lottery.presale;
+    lottery_ticket.user = ctx.accounts.user.key();
+    lottery_ticket.index = lottery.registrants;
+    lottery_ticket.bump = ctx.bumps.lottery_ticket;
+    lottery.registrants = lottery.registrants.checked_add(1).ok_or(PresaleError::CalculationError)?;
+
+    emit!(LotteryRegistered {
+        presale: lottery.presale,
+        user: lottery_ticket.user,
+        ticket_index: lottery_ticket.index,
+        timestamp: current_time,
+    });
+    Ok(())
+}
+
+/// Cualquiera puede cerrar el registro una vez vencida la ventana; fija el slot
+/// cuyo hash se usará en el sorteo.
+pub fn close_lottery_registration(ctx: Context<CloseLotteryRegistration>) -> Result<()> {
+    let clock = Clock::get()?;
+    let lottery = &mut ctx.accounts.lottery;
+    require!(lottery.draw_slot == 0, PresaleError::LotteryRegistrationClosed);
+    require!(clock.unix_timestamp > lottery.registration_end, PresaleError::LotteryNotReady);
+
+    lottery.draw_slot = clock.slot + DRAW_SLOT_DELAY;
+
+    emit!(LotteryRegistrationClosed {
+        presale: lottery.presale,
+        registrants: lottery.registrants,
+        draw_slot: lottery.draw_slot,
+        timestamp: clock.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Revela el secreto y fija la semilla del sorteo. Debe hacerse dentro de
+/// `DRAW_REVEAL_WINDOW` slots desde `draw_slot`; después solo cabe vencerlo.
+pub fn draw_lottery(ctx: Context<DrawLottery>, secret: [u8; 32]) -> Result<()> {
+    let lottery = &mut ctx.accounts.lottery;
+    require!(!lottery.drawn, PresaleError::LotteryAlreadyDrawn);
+    require!(lottery.draw_slot != 0, PresaleError::LotteryNotReady);
+    require!(
+        Clock::get()?.slot <= lottery.draw_slot.saturating_add(DRAW_REVEAL_WINDOW),
+        PresaleError::LotteryDrawExpired
+    );
+    require!(
+        keccak::hashv(&[&secret]).to_bytes() == lottery.commitment,
+        PresaleError::InvalidLotteryReveal
+    );
+
+    let slot_hash = slot_hash_at(&ctx.accounts.slot_hashes, lottery.draw_slot)?;
+    let seed = keccak::hashv(&[&secret, &slot_hash, lottery.presale.as_ref()]).to_bytes();
+    let (stride, offset) = lottery_permutation(&seed, lottery.registrants);
+    lottery.seed = seed;
+    lottery.stride = stride;
+    lottery.offset = offset;
+    lottery.drawn = true;
+
+    emit!(LotteryDrawn {
+        presale: lottery.presale,
+        secret,
+        slot_hash,
+        seed,
+        stride,
+        offset,
+        registrants: lottery.registrants,
+        winners: lottery.winner_count.min(lottery.registrants),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Cualquiera puede vencer un sorteo cuyo secreto no se reveló a tiempo: todos
+/// los registrados pasan a ganar con `fallback_allocation`.
+pub fn expire_lottery_draw(ctx: Context<ExpireLotteryDraw>) -> Result<()> {
+    let clock = Clock::get()?;
+    let lottery = &mut ctx.accounts.lottery;
+    require!(!lottery.drawn, PresaleError::LotteryAlreadyDrawn);
+    require!(
+        lottery.draw_slot != 0 && clock.slot > lottery.draw_slot.saturating_add(DRAW_REVEAL_WINDOW),
+        PresaleError::LotteryNotReady
+    );
+
+    lottery.drawn = true;
+    lottery.fallback = true;
+
+    emit!(LotteryDrawExpired {
+        presale: lottery.presale,
+        draw_slot: lottery.draw_slot,
+        registrants: lottery.registrants,
+        allocation_per_registrant: lottery.fallback_allocation(),
+        timestamp: clock.unix_timestamp,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn seeds() -> impl Iterator<Item = [u8; 32]> {
+        (0u32..64).map(|index| keccak::hashv(&[&index.to_le_bytes()]).to_bytes())
+    }
+
+    fn drawn_lottery(seed: &[u8; 32], registrants: u32, winner_count: u32) -> Lottery {
+        let (stride, offset) = lottery_permutation(seed, registrants);
+        Lottery {
+            registrants,
+            winner_count,
+            allocation_per_winner: 1_000,
+            stride,
+            offset,
+            drawn: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stride_is_coprime_with_registrants() {
+        for registrants in [1, 2, 3, 4, 6, 10, 12, 30, 64, 97, 210, 1_000] {
+            for seed in seeds() {
+                let (stride, offset) = lottery_permutation(&seed, registrants);
+                assert!(stride >= 1 && stride <= registrants as u64);
+                assert!(offset < registrants as u64);
+                assert_eq!(gcd(stride, registrants as u64), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn permutation_is_a_bijection() {
+        for registrants in [1, 7, 12, 100] {
+            for seed in seeds() {
+                let lottery = drawn_lottery(&seed, registrants, 1);
+                let positions: HashSet<u128> = (0..registrants)
+                    .map(|index| (lottery.stride as u128 * index as u128 + lottery.offset as u128) % registrants as u128)
+                    .collect();
+                assert_eq!(positions.len(), registrants as usize);
+            }
+        }
+    }
+
+    #[test]
+    fn winners_are_unique_and_exactly_winner_count() {
+        for (registrants, winner_count) in [(10, 3), (12, 4), (100, 10), (5, 5), (3, 8)] {
+            for seed in seeds() {
+                let lottery = drawn_lottery(&seed, registrants, winner_count);
+                let winners: HashSet<u32> = (0..registrants).filter(|&index| lottery.is_winner(index)).collect();
+                assert_eq!(winners.len() as u32, winner_count.min(registrants));
+            }
+        }
+    }
+
+    #[test]
+    fn nobody_wins_before_the_draw() {
+        let lottery = Lottery { drawn: false, ..drawn_lottery(&[7u8; 32], 10, 10) };
+        assert!((0..10).all(|index| !lottery.is_winner(index)));
+        // Un boleto fuera del rango de registrados nunca gana
+        assert!(!drawn_lottery(&[7u8; 32], 10, 10).is_winner(10));
+    }
+
+    #[test]
+    fn fallback_splits_the_allocation_among_all_registrants() {
+        let lottery = Lottery { fallback: true, ..drawn_lottery(&[0u8; 32], 7, 3) };
+        assert!((0..7).all(|index| lottery.is_winner(index)));
+        assert_eq!(lottery.fallback_allocation(), 3 * 1_000 / 7);
+        assert!(lottery.fallback_allocation() * 7 <= 3 * 1_000);
+
+        // Con menos registrados que ganadores cada uno recibe la asignación completa
+        let lottery = Lottery { fallback: true, ..drawn_lottery(&[0u8; 32], 2, 3) };
+        assert_eq!(lottery.fallback_allocation(), 1_000);
+    }
+}
@@ -0,0 +1,54 @@
+use anchor_lang::prelude::*;
+use crate::{Presale, PresaleError};
+
+/// Ronda de venta dentro de la ventana de la preventa (privada, whitelist,
+/// pública...). Los topes en 0 significan sin límite propio de la fase.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct SalePhase {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub price: u64,
+    /// Si es `false`, cualquier wallet puede comprar durante la fase.
+    pub whitelist_required: bool,
+    pub max_per_wallet: u64,
+    pub supply_cap: u64,
+    /// Progreso de la fase; se inicializa en 0 al crear la preventa.
+    pub tokens_sold: u64,
+    pub total_raised: u64,
+}
+
+impl SalePhase {
+    pub const LEN: usize = 8 + 8 + 8 + 1 + 8 + 8 + 8 + 8;
+    pub const MAX_PHASES: usize = 5;
+
+    pub fn is_active(&self, current_time: i64) -> bool {
+        current_time >= self.start_time && current_time <= self.end_time
+    }
+}
+
+/// Las fases deben estar ordenadas, sin solaparse y dentro de la ventana de la preventa.
+pub fn validate_phases(phases: &[SalePhase], start_time: i64, end_time: i64) -> Result<()> {
+    if phases.is_empty() {
+        return Ok(());
+    }
+    require!(phases.len() <= SalePhase::MAX_PHASES, PresaleError::InvalidPhases);
+    require!(
+        phases.iter().all(|phase| phase.price > 0 && phase.start_time < phase.end_time)
+            && phases.windows(2).all(|pair| pair[0].end_time < pair[1].start_time)
+            && phases[0].start_time >= start_time
+            && phases[phases.len() - 1].end_time <= end_time,
+        PresaleError::InvalidPhases
+    );
+    Ok(())
+}
+
+impl Presale {
+    /// Fase vigente con su índice; `None` si la preventa no usa fases o si se
+    /// está entre dos fases.
+    pub fn active_phase(&self, current_time: i64) -> Option<(usize, &SalePhase)> {
+        self.phases
+            .iter()
+            .enumerate()
+            .find(|(_, phase)| phase.is_active(current_time))
+    }
+}
@@ -1,7 +1,16 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer as TokenTransfer};
-use crate::{GlobalStats, PresaleError, PresaleInfo};
-use crate::vesting::VestingInfo;
+use anchor_spl::token::spl_token;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{GlobalStats, PresaleError, PresaleInfo, ProgramConfig, RoleAssignment};
+use crate::escrow::deposit_tokens;
+use crate::events::PresaleCreated;
+use crate::fees::{FeeConfig, FeeExemption, FEE_VAULT_SEED};
+use crate::liquidity::LiquidityLockConfig;
+use crate::allocation::PurchaseLimits;
+use crate::phases::{validate_phases, SalePhase};
+use crate::referral::MAX_REFERRAL_BPS;
+use crate::pricing::{validate_price_tiers, DutchAuction, PriceTier};
+use crate::vesting::VestingSchedule;
 
 #[derive(Accounts)]
 pub struct InitializePresale<'info> {
@@ -9,19 +18,92 @@ pub struct InitializePresale<'info> {
     pub presale: Account<'info, Presale>,
     #[account(mut)]
     pub creator: Signer<'info>,
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        constraint = !config.circuit_breaker.launches_paused @ PresaleError::ProtocolPaused,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(
+        init,
+        payer = creator,
+        space = RoleAssignment::LEN,
+        seeds = [RoleAssignment::SEED, presale.key().as_ref(), creator.key().as_ref()],
+        bump,
+    )]
+    pub creator_role: Account<'info, RoleAssignment>,
     #[account(mut)]
-    pub creator_token_account: Account<'info, TokenAccount>,
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
     #[account(mut)]
-    pub token_vault: Account<'info, TokenAccount>,
+    pub token_vault: InterfaceAccount<'info, TokenAccount>,
+    /// Mint del token de venta; puede ser del programa clásico o de Token-2022
+    #[account(address = token_vault.mint @ PresaleError::InvalidSaleMint)]
+    pub sale_mint: InterfaceAccount<'info, Mint>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    #[account(mut)]
-    pub commission_vault: Account<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    #[account(seeds = [FeeConfig::SEED], bump = fee_config.bump)]
+    pub fee_config: Account<'info, FeeConfig>,
+    /// CHECK: PDA del protocolo que recibe la comisión de creación
+    #[account(mut, seeds = [FEE_VAULT_SEED], bump = fee_config.fee_vault_bump)]
+    pub fee_vault: AccountInfo<'info>,
+    /// Solo si el creador está exento de comisiones
+    #[account(seeds = [FeeExemption::SEED, creator.key().as_ref()], bump = fee_exemption.bump)]
+    pub fee_exemption: Option<Account<'info, FeeExemption>>,
     #[account(mut)]
     pub global_stats: Account<'info, GlobalStats>,
+    /// CHECK: PDA `[presale]`; se verifica contra el bump en `initialize_presale`
+    #[account(mut)]
+    pub sale_authority: AccountInfo<'info>,
+    /// Solo en modo `RaiseMode::SplToken`: mint en el que se recauda
+    pub raise_mint: Option<InterfaceAccount<'info, Mint>>,
+    /// Solo en modo `RaiseMode::SplToken`: bóveda que recibe los pagos
+    pub raise_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+}
+
+/// Moneda en la que los compradores pagan la preventa.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RaiseMode {
+    #[default]
+    NativeSol,
+    SplToken,
+}
+
+/// Ciclo de vida de la preventa.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PresaleStatus {
+    #[default]
+    Active,
+    /// Terminó sin alcanzar `raise_goal`: los compradores pueden reembolsarse.
+    Failed,
+    /// Liquidada con `finalize_presale`: no admite más compras.
+    Finalized,
+}
+
+/// Forma de repartir el suministro entre los compradores.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SaleMode {
+    /// Cada compra se atiende por orden de llegada hasta agotar la asignación.
+    #[default]
+    FirstComeFirstServed,
+    /// Se aceptan depósitos hasta `deposit_cap` por wallet (0 = sin límite) y, si
+    /// hay sobredemanda, `total_tokens` se reparte a prorrata al terminar.
+    Overflow { deposit_cap: u64 },
+}
+
+impl SaleMode {
+    pub const LEN: usize = 1 + 8;
+}
+
+/// Qué hacer con los tokens no vendidos al liquidar la preventa.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnsoldTokenAction {
+    #[default]
+    Return,
+    Burn,
 }
 
 #[account]
+#[derive(Default)]
 pub struct Presale {
     pub id: u64,
     pub creator: Pubkey,
@@ -37,15 +119,49 @@ pub struct Presale {
     pub total_raised: u64,
     pub total_investors: u32,
     pub paused: bool,
-    pub whitelist: Vec<Pubkey>,
-    pub allocations: Vec<(Pubkey, u64)>,
-    pub buyer_purchases: Vec<(Pubkey, u64)>,
-    pub vestings: Vec<(Pubkey, VestingInfo)>,
+    /// Wallets en la lista blanca explícita; cada una tiene su `WhitelistEntry`.
+    pub whitelist_size: u32,
+    /// Wallets con asignación explícita en su `WhitelistEntry`.
+    pub allocation_count: u32,
+    /// Tope opcional del creador para la lista blanca y para las asignaciones
+    /// explícitas; 0 = sin tope.
+    pub max_entries: u64,
     pub commission_paid: u64,
     pub bump: u8,
     pub token_vault: Pubkey,
-    pub claims: Vec<(Pubkey, bool)>,
-    pub max_entries: u64,
+    pub merkle_root: [u8; 32],
+    pub merkle_allocations: bool,
+    pub vesting_schedule: VestingSchedule,
+    pub raise_mode: RaiseMode,
+    pub raise_vault: Pubkey,
+    pub status: PresaleStatus,
+    pub unsold_action: UnsoldTokenAction,
+    pub price_tiers: Vec<PriceTier>,
+    pub dutch_auction: Option<DutchAuction>,
+    pub clearing_price: u64,
+    pub sale_mode: SaleMode,
+    pub raise_fee_bps: u16,
+    /// Recompensa por referido en puntos básicos del costo; 0 desactiva los referidos.
+    pub referral_bps: u16,
+    pub referred_raised: u64,
+    /// Recompensas acumuladas por todos los referidores, antes de liquidar.
+    pub referral_rewards: u64,
+    pub referral_rewards_claimed: u64,
+    pub purchase_limits: PurchaseLimits,
+    pub phases: Vec<SalePhase>,
+    /// Tokens de asignación por punto de peso del nivel de staking del comprador;
+    /// 0 desactiva la asignación por staking.
+    pub tokens_per_tier_weight: u64,
+    pub liquidity_lock: LiquidityLockConfig,
+    /// Asignaciones de equipo creadas y sus totales; los tokens están en la
+    /// bóveda de cada `TeamGrant`, no en el token_vault.
+    pub team_grants: u32,
+    pub team_granted: u64,
+    pub team_claimed: u64,
+    /// Tokens devueltos a tesorería al revocar asignaciones de equipo.
+    pub team_revoked: u64,
+    /// Si los traspasos de beneficiario necesitan la aprobación de un operador.
+    pub transfer_requires_approval: bool,
 }
 
 impl Presale {
@@ -64,19 +180,123 @@ impl Presale {
         8 + // total_raised
         4 + // total_investors
         1 + // paused
-        (4 + 32 * 1000) + // whitelist (assume max 1000 entries)
-        (4 + (32 + 8) * 1000) + // allocations (max 1000)
-        (4 + (32 + 8) * 1000) + // buyer_purchases (max 1000)
-        (4 + (32 + VestingInfo::LEN) * 1000) + // vestings (max 1000)
+        4 + // whitelist_size
+        4 + // allocation_count
+        8 + // max_entries
         8 + // commission_paid
         1 + // bump
         32 + // token_vault
-        (4 + (32 + 1) * 1000) + // claims (max 1000)
-        8; // max_entries
+        32 + // merkle_root
+        1 + // merkle_allocations
+        VestingSchedule::LEN + // vesting_schedule
+        1 + // raise_mode
+        32 + // raise_vault
+        1 + // status
+        1 + // unsold_action
+        (4 + PriceTier::LEN * PriceTier::MAX_TIERS) + // price_tiers
+        (1 + DutchAuction::LEN) + // dutch_auction
+        8 + // clearing_price
+        SaleMode::LEN + // sale_mode
+        2 + // raise_fee_bps
+        2 + // referral_bps
+        8 + // referred_raised
+        8 + // referral_rewards
+        8 + // referral_rewards_claimed
+        PurchaseLimits::LEN + // purchase_limits
+        (4 + SalePhase::LEN * SalePhase::MAX_PHASES) + // phases
+        8 + // tokens_per_tier_weight
+        LiquidityLockConfig::LEN + // liquidity_lock
+        4 + // team_grants
+        8 + // team_granted
+        8 + // team_claimed
+        8 + // team_revoked
+        1; // transfer_requires_approval
+
+    /// Una raíz vacía indica que la preventa usa la lista blanca explícita.
+    pub fn uses_merkle_whitelist(&self) -> bool {
+        self.merkle_root != [0u8; 32]
+    }
+
+    /// `raise_goal` actúa como soft cap: una preventa sin meta nunca falla. En una
+    /// subasta que liquida al precio de cierre solo cuenta lo que se queda la
+    /// venta, no los depósitos brutos.
+    pub fn soft_cap_reached(&self) -> bool {
+        let raised = match &self.dutch_auction {
+            Some(auction) if auction.settle_at_clearing_price => self.clearing_proceeds(),
+            _ => self.total_raised,
+        };
+        raised >= self.raise_goal
+    }
+
+    /// Límites que deben cumplir los parámetros editables de la preventa.
+    pub fn validate_parameters(&self) -> Result<()> {
+        require!(self.price > 0, PresaleError::InvalidParameters);
+        require!(
+            self.start_time < self.end_time && self.end_time <= self.vesting_end_time,
+            PresaleError::InvalidParameters
+        );
+        require!(
+            self.total_tokens >= self.tokens_sold || matches!(self.sale_mode, SaleMode::Overflow { .. }),
+            PresaleError::InvalidParameters
+        );
+        validate_price_tiers(&self.price_tiers, self.total_tokens)?;
+        if let Some(auction) = &self.dutch_auction {
+            auction.validate()?;
+            require!(self.price_tiers.is_empty(), PresaleError::InvalidDutchAuction);
+        }
+        if let SaleMode::Overflow { .. } = self.sale_mode {
+            // El reparto a prorrata asume un precio fijo
+            require!(
+                self.price_tiers.is_empty() && self.dutch_auction.is_none(),
+                PresaleError::InvalidParameters
+            );
+        }
+        require!(self.referral_bps <= MAX_REFERRAL_BPS, PresaleError::InvalidParameters);
+        self.purchase_limits.validate()?;
+        self.liquidity_lock.validate()?;
+        validate_phases(&self.phases, self.start_time, self.end_time)?;
+        require!(
+            self.max_entries == 0 || self.max_entries >= self.whitelist_size.max(self.allocation_count) as u64,
+            PresaleError::InvalidParameters
+        );
+        if !self.phases.is_empty() {
+            // Cada fase fija su propio precio
+            require!(
+                self.price_tiers.is_empty()
+                    && self.dutch_auction.is_none()
+                    && self.sale_mode == SaleMode::FirstComeFirstServed,
+                PresaleError::InvalidPhases
+            );
+        }
+        Ok(())
+    }
+
+    /// En modo por orden de llegada, si ya se vendió toda la asignación.
+    pub fn is_sold_out(&self) -> bool {
+        self.sale_mode == SaleMode::FirstComeFirstServed && self.tokens_sold >= self.total_tokens
+    }
+
+    /// En modo overflow, si se pidieron más tokens de los que hay a la venta.
+    pub fn is_oversubscribed(&self) -> bool {
+        matches!(self.sale_mode, SaleMode::Overflow { .. }) && self.tokens_sold > self.total_tokens
+    }
+
+    /// Marca la preventa como fallida si terminó por debajo del soft cap.
+    pub fn refresh_status(&mut self, current_time: i64) {
+        if self.status == PresaleStatus::Active && current_time > self.end_time && !self.soft_cap_reached() {
+            self.status = PresaleStatus::Failed;
+        }
+    }
+}
+
+/// PDA `[presale]` que custodia el token_vault y la bóveda de recaudación.
+pub fn sale_authority_address(presale: &Pubkey, bump: u8) -> Result<Pubkey> {
+    Pubkey::create_program_address(&[presale.as_ref(), &[bump]], &crate::ID)
+        .map_err(|_| error!(PresaleError::InvalidSaleAuthority))
 }
 
-pub fn initialize_presale(
-    ctx: Context<InitializePresale>,
+pub fn initialize_presale<'info>(
+    ctx: Context<'_, '_, '_, 'info, InitializePresale<'info>>,
     id: u64,
     total_tokens: u64,
     price: u64,
@@ -86,7 +306,21 @@ pub fn initialize_presale(
     raise_goal: u64,
     bump: u8,
     max_entries: u64,
+    vesting_schedule: VestingSchedule,
+    raise_mode: RaiseMode,
+    unsold_action: UnsoldTokenAction,
+    price_tiers: Vec<PriceTier>,
+    dutch_auction: Option<DutchAuction>,
+    sale_mode: SaleMode,
+    referral_bps: u16,
+    purchase_limits: PurchaseLimits,
+    phases: Vec<SalePhase>,
+    tokens_per_tier_weight: u64,
+    liquidity_lock: LiquidityLockConfig,
+    transfer_requires_approval: bool,
 ) -> Result<()> {
+    vesting_schedule.validate()?;
+
     let presale = &mut ctx.accounts.presale;
     let creator = ctx.accounts.creator.key();
     let global_stats = &mut ctx.accounts.global_stats;
@@ -97,12 +331,29 @@ pub fn initialize_presale(
         PresaleError::DuplicatePresaleId
     );
 
-    // Verificar que la comisión vault es una cuenta de token
+    // Las bóvedas deben estar bajo la autoridad PDA de la preventa
+    let sale_authority = sale_authority_address(&presale.key(), bump)?;
+    require!(
+        ctx.accounts.sale_authority.key() == sale_authority,
+        PresaleError::InvalidSaleAuthority
+    );
     require!(
-        ctx.accounts.commission_vault.mint == ctx.accounts.token_program.key(),
-        PresaleError::InvalidCommissionVault
+        ctx.accounts.token_vault.owner == sale_authority,
+        PresaleError::InvalidSaleAuthority
     );
 
+    // Determinar la moneda de recaudación
+    let (raise_token, raise_vault) = match raise_mode {
+        RaiseMode::NativeSol => (spl_token::native_mint::ID, Pubkey::default()),
+        RaiseMode::SplToken => {
+            let raise_mint = ctx.accounts.raise_mint.as_ref().ok_or(PresaleError::InvalidRaiseToken)?;
+            let raise_vault = ctx.accounts.raise_vault.as_ref().ok_or(PresaleError::InvalidRaiseVault)?;
+            require!(raise_vault.mint == raise_mint.key(), PresaleError::InvalidRaiseVault);
+            require!(raise_vault.owner == sale_authority, PresaleError::InvalidRaiseVault);
+            (raise_mint.key(), raise_vault.key())
+        }
+    };
+
     presale.id = id;
     presale.creator = creator;
     presale.total_tokens = total_tokens;
@@ -115,41 +366,101 @@ pub fn initialize_presale(
     presale.total_raised = 0;
     presale.total_investors = 0;
     presale.paused = false;
-    presale.whitelist = Vec::new();
-    presale.allocations = Vec::new();
-    presale.buyer_purchases = Vec::new();
-    presale.vestings = Vec::new();
+    presale.whitelist_size = 0;
+    presale.allocation_count = 0;
+    presale.max_entries = max_entries;
     presale.commission_paid = 0;
+    presale.raise_fee_bps = 0;
     presale.bump = bump;
     presale.token_vault = ctx.accounts.token_vault.key();
-    presale.claims = Vec::new();
-    presale.max_entries = max_entries;
-    presale.raise_token = ctx.accounts.token_program.key();
+    presale.merkle_root = [0u8; 32];
+    presale.merkle_allocations = false;
+    presale.vesting_schedule = vesting_schedule;
+    presale.raise_mode = raise_mode;
+    presale.raise_token = raise_token;
+    presale.raise_vault = raise_vault;
+    presale.status = PresaleStatus::Active;
+    presale.unsold_action = unsold_action;
+    presale.price_tiers = price_tiers;
+    presale.clearing_price = dutch_auction.as_ref().map(|auction| auction.start_price).unwrap_or(0);
+    presale.dutch_auction = dutch_auction;
+    presale.sale_mode = sale_mode;
+    presale.referral_bps = referral_bps;
+    presale.referred_raised = 0;
+    presale.referral_rewards = 0;
+    presale.referral_rewards_claimed = 0;
+    presale.purchase_limits = purchase_limits;
+    presale.phases = phases
+        .into_iter()
+        .map(|phase| SalePhase { tokens_sold: 0, total_raised: 0, ..phase })
+        .collect();
+    presale.tokens_per_tier_weight = tokens_per_tier_weight;
+    presale.liquidity_lock = liquidity_lock;
+    presale.team_grants = 0;
+    presale.team_granted = 0;
+    presale.team_claimed = 0;
+    presale.team_revoked = 0;
+    presale.transfer_requires_approval = transfer_requires_approval;
+    presale.validate_parameters()?;
+
+    // El creador es el owner inicial y recibe todos los roles
+    let creator_role = &mut ctx.accounts.creator_role;
+    creator_role.presale = presale.key();
+    creator_role.holder = creator;
+    creator_role.roles = RoleAssignment::ALL_ROLES;
+    creator_role.bump = ctx.bumps.creator_role;
     presale.sale_token = ctx.accounts.token_vault.mint;
 
-    // Calcular la comisión
-    let duration_weeks = (end_time - start_time) / (7 * 24 * 60 * 60) + 1;
-    let commission = duration_weeks as u64 * 100_000_000u64; // 0.1 SOL por semana en lamports
-
-    // Transferir la comisión
-    let cpi_context = CpiContext::new(
-        ctx.accounts.system_program.to_account_info(),
-        anchor_lang::system_program::Transfer {
-            from: ctx.accounts.creator.to_account_info(),
-            to: ctx.accounts.commission_vault.to_account_info(),
-        },
-    );
-    anchor_lang::system_program::transfer(cpi_context, commission.try_into().unwrap())?;
+    // Cobrar la comisión de creación y fijar la comisión sobre lo recaudado con
+    // las tarifas vigentes; los creadores exentos no pagan ninguna de las dos
+    if ctx.accounts.fee_exemption.is_none() {
+        let fee_config = &ctx.accounts.fee_config;
+        let commission = fee_config.launch_fee(start_time, end_time)?;
+        if commission > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.creator.to_account_info(),
+                    to: ctx.accounts.fee_vault.to_account_info(),
+                },
+            );
+            anchor_lang::system_program::transfer(cpi_context, commission)?;
+        }
+        presale.commission_paid = commission;
+        presale.raise_fee_bps = fee_config.raise_fee_bps;
+    }
 
-    // Transferir los tokens del creador al token_vault
-    let cpi_accounts = TokenTransfer {
-        from: ctx.accounts.creator_token_account.to_account_info(),
-        to: ctx.accounts.token_vault.to_account_info(),
-        authority: ctx.accounts.creator.to_account_info(),
-    };
-    token::transfer(
-        CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts),
-        total_tokens,
+    // En modo SOL la autoridad custodia los pagos; se fondea con el mínimo de renta
+    // para que los reembolsos y liquidaciones nunca la dejen por debajo
+    if raise_mode == RaiseMode::NativeSol {
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(0);
+        let missing = rent_exempt_minimum.saturating_sub(ctx.accounts.sale_authority.lamports());
+        if missing > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.creator.to_account_info(),
+                    to: ctx.accounts.sale_authority.to_account_info(),
+                },
+            );
+            anchor_lang::system_program::transfer(cpi_context, missing)?;
+        }
+    }
+
+    // Transferir los tokens del creador al token_vault, junto con los reservados para
+    // el bloqueo de liquidez; si el mint cobra comisión de transferencia la paga el
+    // creador, de modo que la bóveda reciba el monto completo
+    let deposit = total_tokens
+        .checked_add(liquidity_lock.sale_tokens)
+        .ok_or(PresaleError::CalculationError)?;
+    deposit_tokens(
+        &ctx.accounts.token_program,
+        &ctx.accounts.creator_token_account,
+        &ctx.accounts.sale_mint,
+        &mut ctx.accounts.token_vault,
+        &ctx.accounts.creator.to_account_info(),
+        ctx.remaining_accounts,
+        deposit,
     )?;
 
     // Actualizar estadísticas globales
@@ -166,6 +477,31 @@ pub fn initialize_presale(
     global_stats.total_presales += 1;
     global_stats.total_investors = global_stats.total_investors.saturating_add(1);
 
+    emit!(PresaleCreated {
+        presale: presale.key(),
+        id,
+        creator,
+        sale_token: presale.sale_token,
+        raise_token,
+        raise_mode,
+        sale_mode,
+        total_tokens,
+        price,
+        start_time,
+        end_time,
+        vesting_end_time,
+        raise_goal,
+        max_entries,
+        purchase_limits,
+        phase_count: presale.phases.len() as u8,
+        tokens_per_tier_weight,
+        liquidity_lock,
+        transfer_requires_approval,
+        launch_fee: presale.commission_paid,
+        raise_fee_bps: presale.raise_fee_bps,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
     Ok(())
 }
 
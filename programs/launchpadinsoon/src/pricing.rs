@@ -1,6 +1,9 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer as TokenTransfer};
-use crate::{Presale, PresaleError};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{Lottery, LotteryTicket, Presale, PresaleError, PresaleStatus, ProgramConfig, PurchaseRecord, RaiseMode, ReferralRecord, SaleMode, StakePool, UserStake, WhitelistEntry};
+use crate::escrow::deposit_tokens;
+use crate::events::{StakeLockExtended, TokensPurchased};
+use crate::whitelist::{merkle_leaf, verify_merkle_proof};
 
 #[derive(Accounts)]
 pub struct BuyTokens<'info> {
@@ -8,93 +11,288 @@ pub struct BuyTokens<'info> {
     pub presale: Account<'info, Presale>,
     #[account(mut)]
     pub buyer: Signer<'info>,
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        constraint = !config.circuit_breaker.buys_paused @ PresaleError::ProtocolPaused,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    /// CHECK: PDA de la preventa; en modo `RaiseMode::NativeSol` custodia los pagos
+    #[account(mut, seeds = [presale.key().as_ref()], bump = presale.bump)]
+    pub sale_authority: AccountInfo<'info>,
+    /// Solo en modo `RaiseMode::SplToken`: cuenta del comprador en el token de recaudación
     #[account(mut)]
-    pub buyer_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub token_vault: Account<'info, TokenAccount>,
-    /// CHECK: Este es la cuenta que recibe el pago
+    pub buyer_raise_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo en modo `RaiseMode::SplToken`: bóveda de recaudación de la preventa
     #[account(mut)]
-    pub treasury: AccountInfo<'info>,
-    /// CHECK: Esta es la autoridad de la venta
-    pub sale_authority: AccountInfo<'info>,
-    pub token_program: Program<'info, Token>,
+    pub raise_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo en modo `RaiseMode::SplToken`: mint del token de recaudación
+    pub raise_mint: Option<InterfaceAccount<'info, Mint>>,
+    /// Programa del token de recaudación (clásico o Token-2022)
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = PurchaseRecord::LEN,
+        seeds = [PurchaseRecord::SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump,
+    )]
+    pub purchase_record: Account<'info, PurchaseRecord>,
+    pub system_program: Program<'info, System>,
     #[account(mut)]
     pub global_stats: Account<'info, GlobalStats>,
+    /// Solo si la compra fue referida: registro del referidor en esta preventa
+    #[account(
+        mut,
+        seeds = [ReferralRecord::SEED, presale.key().as_ref(), referral_record.referrer.as_ref()],
+        bump = referral_record.bump,
+        constraint = referral_record.referrer != buyer.key() @ PresaleError::InvalidReferrer,
+    )]
+    pub referral_record: Option<Account<'info, ReferralRecord>>,
+    /// Solo para comprar con el nivel de staking: pool del protocolo
+    #[account(seeds = [StakePool::SEED], bump = stake_pool.bump)]
+    pub stake_pool: Option<Account<'info, StakePool>>,
+    /// Solo para comprar con el nivel de staking: stake del comprador
+    #[account(
+        mut,
+        seeds = [UserStake::SEED, buyer.key().as_ref()],
+        bump = user_stake.bump,
+    )]
+    pub user_stake: Option<Account<'info, UserStake>>,
+    /// Solo para comprar con un boleto ganador: sorteo de la preventa
+    #[account(seeds = [Lottery::SEED, presale.key().as_ref()], bump = lottery.bump)]
+    pub lottery: Option<Account<'info, Lottery>>,
+    /// Solo para comprar con un boleto ganador: boleto del comprador
+    #[account(
+        seeds = [LotteryTicket::SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = lottery_ticket.bump,
+    )]
+    pub lottery_ticket: Option<Account<'info, LotteryTicket>>,
+    /// Solo si el comprador está en la lista blanca explícita o tiene asignación
+    #[account(
+        seeds = [WhitelistEntry::SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = whitelist_entry.bump,
+    )]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
 }
 
-pub fn buy_tokens(ctx: Context<BuyTokens>, presale_id: u64, amount: u64) -> Result<()> {
+pub fn buy_tokens<'info>(
+    ctx: Context<'_, '_, '_, 'info, BuyTokens<'info>>,
+    presale_id: u64,
+    amount: u64,
+    merkle_proof: Vec<[u8; 32]>,
+    merkle_allocation: Option<u64>,
+) -> Result<()> {
     let presale = &mut ctx.accounts.presale;
+    let purchase_record = &mut ctx.accounts.purchase_record;
     let global_stats = &mut ctx.accounts.global_stats;
 
     // Verificar que el ID de preventa coincide
     require!(presale.id == presale_id, PresaleError::InvalidPresaleId);
 
-    // Verificar que la preventa no esté pausada
+    // Verificar que la preventa no esté pausada ni cerrada
     require!(!presale.paused, PresaleError::PresalePaused);
+    require!(presale.status == PresaleStatus::Active, PresaleError::PresaleNotActive);
 
-    // Verificar que el comprador está en la lista blanca
-    require!(presale.whitelist.contains(&ctx.accounts.buyer.key()), PresaleError::NotWhitelisted);
+    let current_time = Clock::get()?.unix_timestamp;
 
-    // Obtener la asignación para el comprador
-    let allocation = presale.allocations.iter()
-        .find(|(pubkey, _)| pubkey == &ctx.accounts.buyer.key())
-        .map(|(_, allocation)| *allocation)
-        .unwrap_or(0);
+    // Verificar que la preventa está dentro de su ventana antes de tocar ningún estado
+    require!(
+        current_time >= presale.start_time && current_time <= presale.end_time,
+        PresaleError::PresaleNotActive
+    );
 
-    // Obtener la compra actual del comprador
-    let buyer_purchase = presale.buyer_purchases.iter()
-        .find(|(pubkey, _)| pubkey == &ctx.accounts.buyer.key())
-        .map(|(_, purchase)| *purchase)
-        .unwrap_or(0);
+    // Con fases, solo se compra dentro de alguna de ellas y con sus reglas
+    let phase_index = presale.active_phase(current_time).map(|(index, _)| index);
+    require!(presale.phases.is_empty() || phase_index.is_some(), PresaleError::PresaleNotActive);
+    let whitelist_required = phase_index
+        .map(|index| presale.phases[index].whitelist_required)
+        .unwrap_or(true);
 
-    // Verificar que la cantidad no exceda la asignación disponible
-    require!(amount <= allocation.saturating_sub(buyer_purchase), PresaleError::AllocationExceeded);
+    // Obtener la asignación explícita para el comprador
+    let whitelist_entry = ctx.accounts.whitelist_entry.as_ref();
+    let explicit_allocation = whitelist_entry.and_then(|entry| entry.allocation);
 
-    // Calcular el costo total
-    let cost = presale.price.checked_mul(amount).ok_or(PresaleError::CalculationError)?;
+    // Nivel de staking del comprador y la asignación que le da esta preventa
+    let (stake_tier, tier_allocation) = match (ctx.accounts.stake_pool.as_ref(), ctx.accounts.user_stake.as_ref()) {
+        (Some(stake_pool), Some(user_stake)) => match stake_pool.tier_of(user_stake, current_time) {
+            Some((index, tier)) => match presale.tier_allocation(tier)? {
+                Some(allocation) => (Some(index as u8), Some(allocation)),
+                None => (None, None),
+            },
+            None => (None, None),
+        },
+        _ => (None, None),
+    };
 
-    // Transferir SOL del comprador al treasury
-    {
-        let buyer_info = ctx.accounts.buyer.to_account_info();
-        let buyer_lamports = &mut **buyer_info.lamports.borrow_mut();
-        *buyer_lamports = buyer_lamports.checked_sub(cost).ok_or(PresaleError::CalculationError)?;
+    // Asignación del sorteo si el boleto del comprador ganó
+    let (lottery_ticket, lottery_allocation) = match (ctx.accounts.lottery.as_ref(), ctx.accounts.lottery_ticket.as_ref()) {
+        (Some(lottery), Some(ticket)) => match lottery.allocation_for(ticket) {
+            Some(allocation) => (Some(ticket.index), Some(allocation)),
+            None => (None, None),
+        },
+        _ => (None, None),
+    };
+    let granted_allocation = lottery_allocation.or(tier_allocation);
+
+    // Verificar que el comprador está en la lista blanca (explícita o por raíz Merkle).
+    // Una asignación explícita prevalece sobre la del sorteo y esta sobre la del
+    // nivel de staking
+    let explicit_allocation = if !whitelist_required || (granted_allocation.is_some() && merkle_proof.is_empty()) {
+        // Fase pública, ganador del sorteo o nivel de staking: no se exige lista blanca
+        explicit_allocation.or(granted_allocation)
+    } else if presale.uses_merkle_whitelist() {
+        let leaf_allocation = if presale.merkle_allocations {
+            Some(merkle_allocation.ok_or(PresaleError::InvalidMerkleProof)?)
+        } else {
+            None
+        };
+        let leaf = merkle_leaf(&ctx.accounts.buyer.key(), leaf_allocation);
+        require!(
+            verify_merkle_proof(&merkle_proof, &presale.merkle_root, leaf),
+            PresaleError::InvalidMerkleProof
+        );
+        leaf_allocation.or(explicit_allocation).or(granted_allocation)
+    } else {
+        require!(whitelist_entry.is_some_and(|entry| entry.whitelisted), PresaleError::NotWhitelisted);
+        explicit_allocation.or(granted_allocation)
+    };
+
+    // Límites por transacción y por wallet; sin asignación explícita rige el
+    // tope general de la preventa
+    presale.purchase_limits.check_purchase(amount)?;
+    if let Some(wallet_limit) = presale.wallet_limit(explicit_allocation) {
+        require!(
+            amount <= wallet_limit.saturating_sub(purchase_record.tokens_purchased),
+            PresaleError::AllocationExceeded
+        );
     }
-    {
-        let treasury_info = ctx.accounts.treasury.to_account_info();
-        let treasury_lamports = &mut **treasury_info.lamports.borrow_mut();
-        *treasury_lamports = treasury_lamports.checked_add(cost).ok_or(PresaleError::CalculationError)?;
+    if let Some(index) = phase_index {
+        let phase = &presale.phases[index];
+        let phase_purchased = purchase_record.phase_purchases[index]
+            .checked_add(amount)
+            .ok_or(PresaleError::CalculationError)?;
+        let phase_sold = phase.tokens_sold.checked_add(amount).ok_or(PresaleError::CalculationError)?;
+        require!(
+            (phase.max_per_wallet == 0 || phase_purchased <= phase.max_per_wallet)
+                && (phase.supply_cap == 0 || phase_sold <= phase.supply_cap),
+            PresaleError::PhaseCapExceeded
+        );
     }
 
-    // Transferir tokens del token_vault al comprador
-    let cpi_accounts = TokenTransfer {
-        from: ctx.accounts.token_vault.to_account_info(),
-        to: ctx.accounts.buyer_token_account.to_account_info(),
-        authority: ctx.accounts.sale_authority.to_account_info(),
-    };
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-    token::transfer(cpi_ctx, amount)?;
+    let cost = presale.purchase_cost(amount, current_time)?;
 
-    // Antes de añadir una nueva entrada, verificar si hay espacio suficiente
-    require!(
-        {
-            let current_len = presale.buyer_purchases.len() as u64;
-            current_len < presale.max_entries
-        },
-        PresaleError::InsufficientSpace
-    );
+    match presale.sale_mode {
+        SaleMode::FirstComeFirstServed => {
+            // Verificar que queda suficiente oferta
+            require!(
+                presale.tokens_sold.checked_add(amount).ok_or(PresaleError::CalculationError)? <= presale.total_tokens,
+                PresaleError::InsufficientSupply
+            );
+        }
+        SaleMode::Overflow { deposit_cap } => {
+            // Se acepta sobredemanda; solo se limita el depósito por wallet
+            let deposited = purchase_record.amount_contributed.checked_add(cost).ok_or(PresaleError::CalculationError)?;
+            require!(deposit_cap == 0 || deposited <= deposit_cap, PresaleError::DepositCapExceeded);
+        }
+    }
 
-    // Actualizar el estado de la preventa
-    presale.tokens_sold = presale.tokens_sold.checked_add(amount).ok_or(PresaleError::CalculationError)?;
+    // Cobrar el pago en la moneda de recaudación; queda en custodia hasta que la
+    // preventa se liquide o se reembolse
+    match presale.raise_mode {
+        RaiseMode::NativeSol => {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.buyer.to_account_info(),
+                    to: ctx.accounts.sale_authority.to_account_info(),
+                },
+            );
+            anchor_lang::system_program::transfer(cpi_context, cost)?;
+        }
+        RaiseMode::SplToken => {
+            let buyer_raise_account = ctx.accounts.buyer_raise_account.as_ref().ok_or(PresaleError::InvalidRaiseToken)?;
+            let raise_vault = ctx.accounts.raise_vault.as_mut().ok_or(PresaleError::InvalidRaiseVault)?;
+            let raise_mint = ctx.accounts.raise_mint.as_ref().ok_or(PresaleError::InvalidRaiseToken)?;
+            let token_program = ctx.accounts.token_program.as_ref().ok_or(PresaleError::InvalidRaiseVault)?;
+            require!(
+                buyer_raise_account.mint == presale.raise_token
+                    && buyer_raise_account.owner == ctx.accounts.buyer.key(),
+                PresaleError::InvalidRaiseToken
+            );
+            require!(raise_mint.key() == presale.raise_token, PresaleError::InvalidRaiseToken);
+            require!(
+                raise_vault.key() == presale.raise_vault && raise_vault.mint == presale.raise_token,
+                PresaleError::InvalidRaiseVault
+            );
 
-    if let Some((_, purchase)) = presale.buyer_purchases.iter_mut().find(|(pubkey, _)| pubkey == &ctx.accounts.buyer.key()) {
-        *purchase = purchase.checked_add(amount).ok_or(PresaleError::CalculationError)?;
-    } else {
-        presale.buyer_purchases.push((ctx.accounts.buyer.key(), amount));
+            // El comprador paga aparte la comisión de transferencia del mint: la
+            // custodia recibe `cost` completo, que es lo que se registra como aportado
+            deposit_tokens(
+                token_program,
+                buyer_raise_account,
+                raise_mint,
+                raise_vault,
+                &ctx.accounts.buyer.to_account_info(),
+                ctx.remaining_accounts,
+                cost,
+            )?;
+        }
     }
 
+    // Registrar la compra en el PDA del comprador; los tokens se entregan con claim_tokens
+    // según el calendario de vesting de la preventa
+    let is_new_buyer = purchase_record.is_new();
+    if is_new_buyer {
+        purchase_record.presale = presale.key();
+        purchase_record.buyer = ctx.accounts.buyer.key();
+        purchase_record.bump = ctx.bumps.purchase_record;
+    }
+    purchase_record.tokens_purchased = purchase_record.tokens_purchased.checked_add(amount).ok_or(PresaleError::CalculationError)?;
+    purchase_record.amount_contributed = purchase_record.amount_contributed.checked_add(cost).ok_or(PresaleError::CalculationError)?;
+    if let Some(index) = phase_index {
+        purchase_record.phase_purchases[index] += amount;
+        let phase = &mut presale.phases[index];
+        phase.tokens_sold += amount;
+        phase.total_raised = phase.total_raised.checked_add(cost).ok_or(PresaleError::CalculationError)?;
+    }
+
+    // Actualizar el estado de la preventa
+    presale.tokens_sold = presale.tokens_sold.checked_add(amount).ok_or(PresaleError::CalculationError)?;
     presale.total_raised = presale.total_raised.checked_add(cost).ok_or(PresaleError::CalculationError)?;
-    presale.total_investors = presale.total_investors.saturating_add(1);
+    if is_new_buyer {
+        presale.total_investors = presale.total_investors.saturating_add(1);
+    }
+    // Acreditar al referidor su parte del costo; se paga de lo recaudado al liquidar
+    let referral_reward = match ctx.accounts.referral_record.as_mut() {
+        Some(referral_record) => {
+            let reward = presale.referral_reward(cost)?;
+            referral_record.referred_purchases = referral_record.referred_purchases.saturating_add(1);
+            referral_record.referred_volume = referral_record.referred_volume.checked_add(cost).ok_or(PresaleError::CalculationError)?;
+            referral_record.accrued_rewards = referral_record.accrued_rewards.checked_add(reward).ok_or(PresaleError::CalculationError)?;
+            presale.referred_raised = presale.referred_raised.checked_add(cost).ok_or(PresaleError::CalculationError)?;
+            presale.referral_rewards = presale.referral_rewards.checked_add(reward).ok_or(PresaleError::CalculationError)?;
+            reward
+        }
+        None => 0,
+    };
+    // El stake que dio nivel queda bloqueado hasta después del cierre, para que no
+    // se pueda hacer stake para una preventa y retirarlo en cuanto se compra
+    if stake_tier.is_some() {
+        if let (Some(stake_pool), Some(user_stake)) = (ctx.accounts.stake_pool.as_ref(), ctx.accounts.user_stake.as_mut()) {
+            user_stake.extend_lock(presale.end_time.saturating_add(stake_pool.lock_duration));
+            emit!(StakeLockExtended {
+                owner: user_stake.owner,
+                presale: presale.key(),
+                locked_until: user_stake.locked_until,
+                timestamp: current_time,
+            });
+        }
+    }
+    if presale.dutch_auction.is_some() {
+        // La compra ya fue aceptada: el precio de cierre es el menor pagado
+        presale.clearing_price = presale.clearing_price.min(presale.current_price(current_time));
+    }
 
     // Actualizar estadísticas globales
     if let Some(presale_info) = global_stats.presales.iter_mut().find(|p| p.id == presale.id) {
@@ -102,18 +300,194 @@ pub fn buy_tokens(ctx: Context<BuyTokens>, presale_id: u64, amount: u64) -> Resu
         presale_info.total_investors = presale.total_investors as u64;
     }
     global_stats.total_raised = global_stats.total_raised.checked_add(cost).ok_or(PresaleError::CalculationError)?;
-    global_stats.total_investors = global_stats.total_investors.saturating_add(1);
+    if is_new_buyer {
+        global_stats.total_investors = global_stats.total_investors.saturating_add(1);
+    }
 
-    // Verificar que la preventa está activa
-    let current_time = Clock::get()?.unix_timestamp;
+    emit!(TokensPurchased {
+        presale: presale.key(),
+        buyer: ctx.accounts.buyer.key(),
+        amount,
+        cost,
+        buyer_tokens_purchased: purchase_record.tokens_purchased,
+        buyer_amount_contributed: purchase_record.amount_contributed,
+        tokens_sold: presale.tokens_sold,
+        total_raised: presale.total_raised,
+        total_investors: presale.total_investors,
+        phase: phase_index.map(|index| index as u8),
+        referrer: ctx.accounts.referral_record.as_ref().map(|record| record.referrer),
+        referral_reward,
+        stake_tier,
+        lottery_ticket,
+        timestamp: current_time,
+    });
+
+    Ok(())
+}
+
+/// Tramo de precio: aplica mientras `tokens_sold` sea menor que `up_to`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PriceTier {
+    pub up_to: u64,
+    pub price: u64,
+}
+
+impl PriceTier {
+    pub const LEN: usize = 8 + 8;
+    pub const MAX_TIERS: usize = 10;
+}
+
+/// Los tramos deben ser crecientes y cubrir todo el suministro a la venta.
+pub fn validate_price_tiers(tiers: &[PriceTier], total_tokens: u64) -> Result<()> {
+    if tiers.is_empty() {
+        return Ok(());
+    }
+    require!(tiers.len() <= PriceTier::MAX_TIERS, PresaleError::InvalidPriceTiers);
     require!(
-        current_time >= presale.start_time && current_time <= presale.end_time,
-        PresaleError::PresaleNotActive
+        tiers.iter().all(|tier| tier.price > 0)
+            && tiers.windows(2).all(|pair| pair[0].up_to < pair[1].up_to)
+            && tiers[tiers.len() - 1].up_to >= total_tokens,
+        PresaleError::InvalidPriceTiers
     );
-
     Ok(())
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PriceDecay {
+    /// El precio baja de forma continua.
+    #[default]
+    Linear,
+    /// El precio baja en escalones de `step_duration` segundos.
+    Stepwise,
+}
+
+/// Subasta holandesa: el precio baja de `start_price` en `start_time` hasta
+/// `floor_price` en `end_time`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct DutchAuction {
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub decay: PriceDecay,
+    pub step_duration: i64,
+    /// Todos pagan el precio de cierre y reclaman la diferencia con `claim_tokens`.
+    pub settle_at_clearing_price: bool,
+}
+
+impl DutchAuction {
+    pub const LEN: usize = 8 + 8 + 1 + 8 + 1;
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.floor_price > 0 && self.start_price >= self.floor_price,
+            PresaleError::InvalidDutchAuction
+        );
+        if self.decay == PriceDecay::Stepwise {
+            require!(self.step_duration > 0, PresaleError::InvalidDutchAuction);
+        }
+        Ok(())
+    }
+
+    pub fn price_at(&self, start_time: i64, end_time: i64, current_time: i64) -> u64 {
+        if current_time <= start_time || end_time <= start_time {
+            return self.start_price;
+        }
+        if current_time >= end_time {
+            return self.floor_price;
+        }
+
+        let (elapsed, duration) = match self.decay {
+            PriceDecay::Linear => ((current_time - start_time) as u128, (end_time - start_time) as u128),
+            PriceDecay::Stepwise => {
+                let step = self.step_duration;
+                let total_steps = (end_time - start_time + step - 1) / step;
+                (((current_time - start_time) / step) as u128, total_steps as u128)
+            }
+        };
+        let drop = (self.start_price - self.floor_price) as u128 * elapsed / duration;
+        self.start_price - drop as u64
+    }
+}
+
+impl Presale {
+    /// Tramo vigente según `tokens_sold`, con su índice.
+    pub fn current_tier(&self) -> Option<(usize, &PriceTier)> {
+        self.price_tiers
+            .iter()
+            .enumerate()
+            .find(|(_, tier)| self.tokens_sold < tier.up_to)
+    }
+
+    pub fn current_price(&self, current_time: i64) -> u64 {
+        if let Some((_, phase)) = self.active_phase(current_time) {
+            return phase.price;
+        }
+        if let Some(auction) = &self.dutch_auction {
+            return auction.price_at(self.start_time, self.end_time, current_time);
+        }
+        self.current_tier().map(|(_, tier)| tier.price).unwrap_or(self.price)
+    }
+
+    /// Costo exacto de comprar `amount` tokens, repartiendo la compra entre los
+    /// tramos que atraviesa.
+    pub fn purchase_cost(&self, amount: u64, current_time: i64) -> Result<u64> {
+        if self.dutch_auction.is_some() || !self.phases.is_empty() {
+            let price = self.current_price(current_time);
+            return Ok(price.checked_mul(amount).ok_or(PresaleError::CalculationError)?);
+        }
+        if self.price_tiers.is_empty() {
+            return Ok(self.price.checked_mul(amount).ok_or(PresaleError::CalculationError)?);
+        }
+
+        let mut sold = self.tokens_sold;
+        let mut remaining = amount;
+        let mut cost: u64 = 0;
+        for tier in self.price_tiers.iter() {
+            if remaining == 0 {
+                break;
+            }
+            if sold >= tier.up_to {
+                continue;
+            }
+            let take = remaining.min(tier.up_to - sold);
+            let tier_cost = tier.price.checked_mul(take).ok_or(PresaleError::CalculationError)?;
+            cost = cost.checked_add(tier_cost).ok_or(PresaleError::CalculationError)?;
+            sold += take;
+            remaining -= take;
+        }
+        require!(remaining == 0, PresaleError::InsufficientSupply);
+
+        Ok(cost)
+    }
+
+    /// Lo que corresponde al creador al liquidar: con precio de cierre uniforme o
+    /// sobredemanda, el excedente queda reservado para los compradores.
+    pub fn settled_proceeds(&self) -> Result<u64> {
+        if self.is_oversubscribed() {
+            return Ok(self.price.checked_mul(self.total_tokens).ok_or(PresaleError::CalculationError)?);
+        }
+        match &self.dutch_auction {
+            Some(auction) if auction.settle_at_clearing_price => Ok(self.clearing_proceeds()),
+            _ => Ok(self.total_raised),
+        }
+    }
+
+    /// Precio al que liquida la subasta: el de la última compra si se agotó, o el
+    /// piso si la ventana termina sin agotarse.
+    pub fn final_clearing_price(&self) -> u64 {
+        match &self.dutch_auction {
+            Some(auction) if !self.is_sold_out() => auction.floor_price,
+            _ => self.clearing_price,
+        }
+    }
+
+    /// Lo que se queda una subasta que liquida al precio de cierre: todo lo
+    /// vendido a `final_clearing_price`, sin superar lo depositado.
+    pub fn clearing_proceeds(&self) -> u64 {
+        let proceeds = self.final_clearing_price() as u128 * self.tokens_sold as u128;
+        proceeds.min(self.total_raised as u128) as u64
+    }
+}
+
 #[account]
 #[derive(Default)]
 pub struct GlobalStats {
@@ -135,3 +509,189 @@ pub struct PresaleInfo {
     pub total_raised: u64,
     pub total_investors: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tramos: 100 tokens a 10, hasta 300 a 20 y hasta 600 a 30.
+    fn tiered_presale(tokens_sold: u64) -> Presale {
+        Presale {
+            price: 10,
+            total_tokens: 600,
+            tokens_sold,
+            price_tiers: vec![
+                PriceTier { up_to: 100, price: 10 },
+                PriceTier { up_to: 300, price: 20 },
+                PriceTier { up_to: 600, price: 30 },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn purchase_within_a_tier_uses_its_price() {
+        assert_eq!(tiered_presale(0).purchase_cost(50, 0).unwrap(), 500);
+        assert_eq!(tiered_presale(150).purchase_cost(100, 0).unwrap(), 2_000);
+    }
+
+    #[test]
+    fn purchase_ending_on_a_boundary_stays_in_the_tier() {
+        let presale = tiered_presale(0);
+        assert_eq!(presale.purchase_cost(100, 0).unwrap(), 1_000);
+        assert_eq!(tiered_presale(100).current_tier().unwrap().0, 1);
+    }
+
+    #[test]
+    fn purchase_crossing_a_boundary_is_split_between_tiers() {
+        // 20 a 10 y 30 a 20
+        assert_eq!(tiered_presale(80).purchase_cost(50, 0).unwrap(), 200 + 600);
+    }
+
+    #[test]
+    fn purchase_crossing_several_boundaries() {
+        // 50 a 10, 200 a 20 y 100 a 30
+        assert_eq!(tiered_presale(50).purchase_cost(350, 0).unwrap(), 500 + 4_000 + 3_000);
+        assert_eq!(tiered_presale(0).purchase_cost(600, 0).unwrap(), 1_000 + 4_000 + 9_000);
+    }
+
+    #[test]
+    fn purchase_beyond_the_last_tier_is_rejected() {
+        assert!(tiered_presale(550).purchase_cost(51, 0).is_err());
+    }
+
+    #[test]
+    fn split_purchases_cost_the_same_as_one_purchase() {
+        let whole = tiered_presale(0).purchase_cost(250, 0).unwrap();
+        let first = tiered_presale(0).purchase_cost(90, 0).unwrap();
+        let second = tiered_presale(90).purchase_cost(160, 0).unwrap();
+        assert_eq!(whole, first + second);
+    }
+
+    #[test]
+    fn tiers_must_increase_and_cover_the_supply() {
+        let tiers = tiered_presale(0).price_tiers;
+        assert!(validate_price_tiers(&tiers, 600).is_ok());
+        assert!(validate_price_tiers(&tiers, 601).is_err());
+        assert!(validate_price_tiers(&[tiers[1].clone(), tiers[0].clone()], 100).is_err());
+        assert!(validate_price_tiers(&[PriceTier { up_to: 600, price: 0 }], 600).is_err());
+    }
+
+    fn auction(decay: PriceDecay, step_duration: i64) -> DutchAuction {
+        DutchAuction {
+            start_price: 1_000,
+            floor_price: 400,
+            decay,
+            step_duration,
+            settle_at_clearing_price: false,
+        }
+    }
+
+    #[test]
+    fn auction_price_at_endpoints() {
+        let auction = auction(PriceDecay::Linear, 0);
+        assert_eq!(auction.price_at(100, 700, 100), 1_000);
+        assert_eq!(auction.price_at(100, 700, 700), 400);
+    }
+
+    #[test]
+    fn auction_price_is_clamped_outside_the_window() {
+        let auction = auction(PriceDecay::Linear, 0);
+        assert_eq!(auction.price_at(100, 700, 0), 1_000);
+        assert_eq!(auction.price_at(100, 700, 10_000), 400);
+        // Una ventana vacía nunca baja del precio inicial
+        assert_eq!(auction.price_at(100, 100, 200), 1_000);
+    }
+
+    #[test]
+    fn linear_auction_price_decays_proportionally() {
+        let auction = auction(PriceDecay::Linear, 0);
+        assert_eq!(auction.price_at(100, 700, 400), 700);
+        assert_eq!(auction.price_at(100, 700, 699), 401);
+    }
+
+    #[test]
+    fn stepwise_auction_price_drops_per_step() {
+        // Tres escalones de 200 segundos: 1000, 800, 600 y el piso al final
+        let auction = auction(PriceDecay::Stepwise, 200);
+        assert_eq!(auction.price_at(100, 700, 299), 1_000);
+        assert_eq!(auction.price_at(100, 700, 300), 800);
+        assert_eq!(auction.price_at(100, 700, 699), 600);
+        assert_eq!(auction.price_at(100, 700, 700), 400);
+    }
+
+    #[test]
+    fn auction_price_never_goes_below_the_floor() {
+        for decay in [PriceDecay::Linear, PriceDecay::Stepwise] {
+            let auction = auction(decay, 7);
+            for current_time in (0..800).step_by(13) {
+                let price = auction.price_at(100, 700, current_time);
+                assert!((400..=1_000).contains(&price));
+            }
+        }
+    }
+
+    #[test]
+    fn clearing_price_settlement_refunds_the_difference() {
+        let presale = Presale {
+            dutch_auction: Some(DutchAuction { settle_at_clearing_price: true, ..auction(PriceDecay::Linear, 0) }),
+            clearing_price: 500,
+            total_tokens: 30,
+            tokens_sold: 30,
+            total_raised: 10 * 900 + 20 * 600,
+            raise_goal: 15_000,
+            status: PresaleStatus::Finalized,
+            ..Default::default()
+        };
+        assert_eq!(presale.settled_proceeds().unwrap(), 30 * 500);
+        assert!(presale.soft_cap_reached());
+
+        let early = PurchaseRecord { tokens_purchased: 10, amount_contributed: 10 * 900, ..Default::default() };
+        let late = PurchaseRecord { tokens_purchased: 20, amount_contributed: 20 * 600, ..Default::default() };
+        assert_eq!(early.refund_due(&presale).unwrap(), 10 * 400);
+        assert_eq!(late.refund_due(&presale).unwrap(), 20 * 100);
+        assert_eq!(
+            early.refund_due(&presale).unwrap() + late.refund_due(&presale).unwrap() + presale.settled_proceeds().unwrap(),
+            presale.total_raised
+        );
+    }
+
+    #[test]
+    fn soft_cap_uses_settled_proceeds_for_clearing_price_auctions() {
+        // Los depósitos brutos superan la meta, pero lo liquidado no
+        let presale = Presale {
+            dutch_auction: Some(DutchAuction { settle_at_clearing_price: true, ..auction(PriceDecay::Linear, 0) }),
+            clearing_price: 400,
+            total_tokens: 30,
+            tokens_sold: 30,
+            total_raised: 30 * 800,
+            raise_goal: 15_000,
+            ..Default::default()
+        };
+        assert!(!presale.soft_cap_reached());
+        let presale = Presale { dutch_auction: None, ..presale };
+        assert!(presale.soft_cap_reached());
+    }
+
+    #[test]
+    fn soft_cap_uses_the_floor_when_the_auction_does_not_sell_out() {
+        // La última compra fue a 800, pero sin agotarse la subasta liquida al piso
+        let presale = Presale {
+            dutch_auction: Some(DutchAuction { settle_at_clearing_price: true, ..auction(PriceDecay::Linear, 0) }),
+            clearing_price: 800,
+            total_tokens: 100,
+            tokens_sold: 30,
+            total_raised: 30 * 900,
+            raise_goal: 20_000,
+            ..Default::default()
+        };
+        assert_eq!(presale.final_clearing_price(), 400);
+        assert_eq!(presale.settled_proceeds().unwrap(), 30 * 400);
+        assert!(!presale.soft_cap_reached());
+
+        // Agotada, liquida al precio de la última compra
+        let presale = Presale { total_tokens: 30, ..presale };
+        assert_eq!(presale.final_clearing_price(), 800);
+        assert!(presale.soft_cap_reached());
+    }
+}
@@ -0,0 +1,179 @@
+use anchor_lang::prelude::*;
+use crate::{Presale, PresaleError, PresaleStatus, SalePhase};
+use crate::vesting::VestingInfo;
+
+#[account]
+#[derive(Default)]
+pub struct PurchaseRecord {
+    pub presale: Pubkey,
+    pub buyer: Pubkey,
+    pub tokens_purchased: u64,
+    pub amount_contributed: u64,
+    pub bump: u8,
+    pub refund_claimed: bool,
+    /// Tokens comprados en cada fase, en el orden de `Presale::phases`.
+    pub phase_purchases: [u64; SalePhase::MAX_PHASES],
+    /// Vesting creado con `create_vesting`; `amount` en 0 si aún no existe.
+    pub vesting: VestingInfo,
+}
+
+impl PurchaseRecord {
+    pub const SEED: &'static [u8] = b"purchase";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // presale
+        32 + // buyer
+        8 + // tokens_purchased
+        8 + // amount_contributed
+        1 + // bump
+        1 + // refund_claimed
+        8 * SalePhase::MAX_PHASES + // phase_purchases
+        VestingInfo::LEN; // vesting
+
+    /// Un registro recién creado por `init_if_needed` todavía no tiene comprador asignado.
+    pub fn is_new(&self) -> bool {
+        self.buyer == Pubkey::default()
+    }
+
+    pub fn has_vesting(&self) -> bool {
+        self.vesting.amount > 0
+    }
+
+    /// Tokens que finalmente corresponden al comprador: en modo overflow con
+    /// sobredemanda, `total_tokens` se reparte a prorrata de lo depositado.
+    pub fn allocated_tokens(&self, presale: &Presale) -> Result<u64> {
+        if !presale.is_oversubscribed() {
+            return Ok(self.tokens_purchased);
+        }
+        let allocated = (presale.total_tokens as u128)
+            .checked_mul(self.amount_contributed as u128)
+            .ok_or(PresaleError::CalculationError)?
+            / presale.total_raised as u128;
+        Ok(allocated as u64)
+    }
+
+    /// Monto a devolver una vez liquidada la preventa: la diferencia con el precio
+    /// de cierre de una subasta holandesa, o la parte no usada del depósito en
+    /// modo overflow.
+    pub fn refund_due(&self, presale: &Presale) -> Result<u64> {
+        if self.refund_claimed || presale.status != PresaleStatus::Finalized {
+            return Ok(0);
+        }
+        if presale.is_oversubscribed() {
+            // Se reparte el excedente a prorrata redondeando hacia abajo, de modo
+            // que la suma de reembolsos nunca supere lo que quedó en custodia
+            let excess = presale.total_raised.saturating_sub(presale.settled_proceeds()?);
+            let refund = (excess as u128)
+                .checked_mul(self.amount_contributed as u128)
+                .ok_or(PresaleError::CalculationError)?
+                / presale.total_raised as u128;
+            return Ok(refund as u64);
+        }
+        match &presale.dutch_auction {
+            Some(auction) if auction.settle_at_clearing_price => {
+                let owed = presale.clearing_price
+                    .checked_mul(self.tokens_purchased)
+                    .ok_or(PresaleError::CalculationError)?;
+                Ok(self.amount_contributed.saturating_sub(owed))
+            }
+            _ => Ok(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SaleMode;
+
+    fn overflow_presale(total_tokens: u64, contributions: &[u64]) -> Presale {
+        let price = 3;
+        Presale {
+            price,
+            total_tokens,
+            tokens_sold: contributions.iter().sum::<u64>() / price,
+            total_raised: contributions.iter().sum(),
+            sale_mode: SaleMode::Overflow { deposit_cap: 0 },
+            status: PresaleStatus::Finalized,
+            ..Default::default()
+        }
+    }
+
+    fn record(amount_contributed: u64, price: u64) -> PurchaseRecord {
+        PurchaseRecord {
+            tokens_purchased: amount_contributed / price,
+            amount_contributed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pro_rata_allocations_never_exceed_total_tokens() {
+        // Aportes que no se dividen exactamente: cada parte se redondea hacia abajo
+        let contributions = [3 * 7, 3 * 11, 3 * 13, 3 * 17, 3 * 19];
+        let presale = overflow_presale(50, &contributions);
+        assert!(presale.is_oversubscribed());
+
+        let allocations: Vec<u64> = contributions.iter()
+            .map(|&contribution| record(contribution, presale.price).allocated_tokens(&presale).unwrap())
+            .collect();
+        let allocated: u64 = allocations.iter().sum();
+        assert!(allocated <= presale.total_tokens);
+        // Lo que se pierde por redondeo es menos de un token por comprador
+        assert!(presale.total_tokens - allocated < contributions.len() as u64);
+        assert_eq!(allocations[0], 50 * 21 / 201);
+    }
+
+    #[test]
+    fn allocation_is_the_purchase_without_oversubscription() {
+        let presale = overflow_presale(100, &[3 * 40, 3 * 60]);
+        assert!(!presale.is_oversubscribed());
+        assert_eq!(record(3 * 40, presale.price).allocated_tokens(&presale).unwrap(), 40);
+    }
+
+    #[test]
+    fn overflow_refunds_never_exceed_the_excess() {
+        let contributions = [3 * 7, 3 * 11, 3 * 13, 3 * 17, 3 * 19];
+        let presale = overflow_presale(50, &contributions);
+        let settled = presale.settled_proceeds().unwrap();
+        assert_eq!(settled, presale.price * presale.total_tokens);
+
+        let refunds: u64 = contributions.iter()
+            .map(|&contribution| record(contribution, presale.price).refund_due(&presale).unwrap())
+            .sum();
+        assert!(settled + refunds <= presale.total_raised);
+        assert!(presale.total_raised - settled - refunds < contributions.len() as u64);
+    }
+
+    #[test]
+    fn overflow_buyer_never_gets_more_than_they_paid_for() {
+        let contributions = [1, 3 * 5, 3 * 1_000, 3 * 333_333];
+        let presale = overflow_presale(7_777, &contributions);
+        for &contribution in &contributions {
+            let purchase_record = record(contribution, presale.price);
+            let allocated = purchase_record.allocated_tokens(&presale).unwrap();
+            let refund = purchase_record.refund_due(&presale).unwrap();
+            assert!(allocated * presale.price + refund <= contribution);
+        }
+    }
+
+    #[test]
+    fn overflow_refund_is_paid_only_once_and_after_finalize() {
+        let presale = overflow_presale(50, &[3 * 40, 3 * 60]);
+        let mut purchase_record = record(3 * 40, presale.price);
+        assert_eq!(purchase_record.refund_due(&presale).unwrap(), 3 * 20);
+
+        purchase_record.refund_claimed = true;
+        assert_eq!(purchase_record.refund_due(&presale).unwrap(), 0);
+
+        let active = Presale { status: PresaleStatus::Active, ..overflow_presale(50, &[3 * 40, 3 * 60]) };
+        assert_eq!(record(3 * 40, active.price).refund_due(&active).unwrap(), 0);
+    }
+
+    #[test]
+    fn overflow_without_oversubscription_refunds_nothing() {
+        let presale = overflow_presale(100, &[3 * 40, 3 * 60]);
+        assert_eq!(presale.settled_proceeds().unwrap(), presale.total_raised);
+        assert_eq!(record(3 * 40, presale.price).refund_due(&presale).unwrap(), 0);
+    }
+}
@@ -0,0 +1,168 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{Presale, PresaleError, PresaleStatus};
+use crate::escrow::pay_from_escrow;
+use crate::events::{ReferralRewardsClaimed, ReferrerRegistered};
+use crate::vesting::BPS_DENOMINATOR;
+
+/// Tope de la recompensa por referido, en puntos básicos del costo de cada compra.
+pub const MAX_REFERRAL_BPS: u16 = 1_000;
+
+/// Recompensas acumuladas por un referidor en una preventa.
+#[account]
+#[derive(Default)]
+pub struct ReferralRecord {
+    pub presale: Pubkey,
+    pub referrer: Pubkey,
+    pub referred_purchases: u32,
+    /// Suma del costo de las compras referidas.
+    pub referred_volume: u64,
+    /// Recompensa acumulada según `referral_bps`, antes de liquidar la preventa.
+    pub accrued_rewards: u64,
+    pub claimed: bool,
+    pub bump: u8,
+}
+
+impl ReferralRecord {
+    pub const SEED: &'static [u8] = b"referral";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // presale
+        32 + // referrer
+        4 + // referred_purchases
+        8 + // referred_volume
+        8 + // accrued_rewards
+        1 + // claimed
+        1; // bump
+
+    /// Recompensa que puede cobrar el referidor una vez liquidada la preventa.
+    pub fn reward_due(&self, presale: &Presale) -> Result<u64> {
+        if self.claimed || presale.status != PresaleStatus::Finalized {
+            return Ok(0);
+        }
+        presale.settled_referral_rewards(self.accrued_rewards)
+    }
+}
+
+impl Presale {
+    /// Recompensa por referido que corresponde a una compra de `cost`.
+    pub fn referral_reward(&self, cost: u64) -> Result<u64> {
+        let reward = (cost as u128)
+            .checked_mul(self.referral_bps as u128)
+            .ok_or(PresaleError::CalculationError)?
+            / BPS_DENOMINATOR as u128;
+        Ok(reward as u64)
+    }
+
+    /// Escala lo acumulado a lo efectivamente liquidado: la parte de los pagos que
+    /// se devuelve (sobredemanda o diferencia de precio) no genera recompensa.
+    /// Redondea hacia abajo, así la suma por referidor nunca supera el total.
+    pub fn settled_referral_rewards(&self, accrued: u64) -> Result<u64> {
+        if self.total_raised == 0 {
+            return Ok(0);
+        }
+        let settled = (accrued as u128)
+            .checked_mul(self.settled_proceeds()? as u128)
+            .ok_or(PresaleError::CalculationError)?
+            / self.total_raised as u128;
+        Ok(settled as u64)
+    }
+}
+
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+    #[account(
+        init,
+        payer = referrer,
+        space = ReferralRecord::LEN,
+        seeds = [ReferralRecord::SEED, presale.key().as_ref(), referrer.key().as_ref()],
+        bump,
+    )]
+    pub referral_record: Account<'info, ReferralRecord>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(mut)]
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [ReferralRecord::SEED, presale.key().as_ref(), referrer.key().as_ref()],
+        bump = referral_record.bump,
+    )]
+    pub referral_record: Account<'info, ReferralRecord>,
+    /// CHECK: PDA de la preventa que custodia los pagos
+    #[account(mut, seeds = [presale.key().as_ref()], bump = presale.bump)]
+    pub sale_authority: AccountInfo<'info>,
+    /// Solo en modo `RaiseMode::SplToken`: recibe la recompensa
+    #[account(mut)]
+    pub referrer_raise_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo en modo `RaiseMode::SplToken`: bóveda de recaudación de la preventa
+    #[account(mut)]
+    pub raise_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo en modo `RaiseMode::SplToken`: mint del token de recaudación
+    pub raise_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Crea el registro del referidor; las compras lo citan pasando su PDA a `buy_tokens`.
+pub fn register_referrer(ctx: Context<RegisterReferrer>) -> Result<()> {
+    require!(ctx.accounts.presale.referral_bps > 0, PresaleError::ReferralsDisabled);
+
+    let referral_record = &mut ctx.accounts.referral_record;
+    referral_record.presale = ctx.accounts.presale.key();
+    referral_record.referrer = ctx.accounts.referrer.key();
+    referral_record.bump = ctx.bumps.referral_record;
+
+    emit!(ReferrerRegistered {
+        presale: referral_record.presale,
+        referrer: referral_record.referrer,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Paga al referidor su recompensa desde lo recaudado, una vez liquidada la preventa.
+pub fn claim_referral_rewards<'info>(ctx: Context<'_, '_, '_, 'info, ClaimReferralRewards<'info>>) -> Result<()> {
+    require!(
+        ctx.accounts.presale.status == PresaleStatus::Finalized,
+        PresaleError::PresaleNotEnded
+    );
+
+    let amount = ctx.accounts.referral_record.reward_due(&ctx.accounts.presale)?;
+    require!(amount > 0, PresaleError::NoTokensToClaim);
+    ctx.accounts.referral_record.claimed = true;
+
+    pay_from_escrow(
+        &ctx.accounts.presale,
+        &ctx.accounts.sale_authority,
+        &ctx.accounts.referrer.to_account_info(),
+        ctx.accounts.referrer_raise_account.as_ref(),
+        ctx.accounts.raise_vault.as_ref(),
+        ctx.accounts.raise_mint.as_ref(),
+        ctx.accounts.token_program.as_ref(),
+        &ctx.accounts.system_program,
+        ctx.remaining_accounts,
+        amount,
+    )?;
+
+    let presale = &mut ctx.accounts.presale;
+    presale.referral_rewards_claimed = presale.referral_rewards_claimed
+        .checked_add(amount)
+        .ok_or(PresaleError::CalculationError)?;
+
+    emit!(ReferralRewardsClaimed {
+        presale: presale.key(),
+        referrer: ctx.accounts.referrer.key(),
+        amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
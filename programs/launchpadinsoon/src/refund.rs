@@ -0,0 +1,123 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{Presale, PresaleError, PresaleStatus, PurchaseRecord};
+use crate::escrow::{pay_from_escrow, transfer_tokens};
+use crate::events::{Refunded, SaleTokensReclaimed};
+
+#[derive(Accounts)]
+pub struct Refund<'info> {
+    #[account(mut)]
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [PurchaseRecord::SEED, presale.key().as_ref(), buyer.key().as_ref()],
+        bump = purchase_record.bump,
+    )]
+    pub purchase_record: Account<'info, PurchaseRecord>,
+    /// CHECK: PDA de la preventa que custodia los pagos
+    #[account(mut, seeds = [presale.key().as_ref()], bump = presale.bump)]
+    pub sale_authority: AccountInfo<'info>,
+    /// Solo en modo `RaiseMode::SplToken`: cuenta del comprador en el token de recaudación
+    #[account(mut)]
+    pub buyer_raise_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo en modo `RaiseMode::SplToken`: bóveda de recaudación de la preventa
+    #[account(mut)]
+    pub raise_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    /// Solo en modo `RaiseMode::SplToken`: mint del token de recaudación
+    pub raise_mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimSaleTokens<'info> {
+    #[account(mut, has_one = creator, has_one = token_vault)]
+    pub presale: Account<'info, Presale>,
+    pub creator: Signer<'info>,
+    #[account(mut)]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub token_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = presale.sale_token @ PresaleError::InvalidSaleMint)]
+    pub sale_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: PDA que firma las transferencias del token_vault
+    #[account(seeds = [presale.key().as_ref()], bump = presale.bump)]
+    pub sale_authority: AccountInfo<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+pub fn refund<'info>(ctx: Context<'_, '_, '_, 'info, Refund<'info>>) -> Result<()> {
+    let presale = &mut ctx.accounts.presale;
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // Solo se reembolsa si la preventa terminó por debajo del soft cap
+    presale.refresh_status(current_time);
+    require!(presale.status == PresaleStatus::Failed, PresaleError::RefundNotAvailable);
+
+    let amount = ctx.accounts.purchase_record.amount_contributed;
+    require!(amount > 0, PresaleError::NoPurchaseFound);
+    // Quien ya recibió tokens no puede además recuperar su aporte
+    require!(ctx.accounts.purchase_record.vesting.claimed_amount == 0, PresaleError::AlreadyClaimed);
+
+    // Devolver exactamente lo aportado; el registro de compra se cierra
+    pay_from_escrow(
+        presale,
+        &ctx.accounts.sale_authority,
+        &ctx.accounts.buyer.to_account_info(),
+        ctx.accounts.buyer_raise_account.as_ref(),
+        ctx.accounts.raise_vault.as_ref(),
+        ctx.accounts.raise_mint.as_ref(),
+        ctx.accounts.token_program.as_ref(),
+        &ctx.accounts.system_program,
+        ctx.remaining_accounts,
+        amount,
+    )?;
+
+    emit!(Refunded {
+        presale: presale.key(),
+        buyer: ctx.accounts.buyer.key(),
+        amount,
+        timestamp: current_time,
+    });
+
+    Ok(())
+}
+
+pub fn reclaim_sale_tokens<'info>(ctx: Context<'_, '_, '_, 'info, ReclaimSaleTokens<'info>>) -> Result<()> {
+    let presale = &mut ctx.accounts.presale;
+    let current_time = Clock::get()?.unix_timestamp;
+
+    presale.refresh_status(current_time);
+    require!(presale.status == PresaleStatus::Failed, PresaleError::RefundNotAvailable);
+
+    let amount = ctx.accounts.token_vault.amount;
+    require!(amount > 0, PresaleError::NoTokensToClaim);
+
+    // Devolver al creador todos los tokens de venta depositados
+    let presale_key = presale.key();
+    let authority_seeds = &[presale_key.as_ref(), &[presale.bump]];
+    let signer = &[&authority_seeds[..]];
+
+    transfer_tokens(
+        &ctx.accounts.token_program,
+        &ctx.accounts.token_vault,
+        &ctx.accounts.sale_mint,
+        &ctx.accounts.creator_token_account,
+        &ctx.accounts.sale_authority,
+        ctx.remaining_accounts,
+        amount,
+        signer,
+    )?;
+
+    emit!(SaleTokensReclaimed {
+        presale: presale_key,
+        creator: ctx.accounts.creator.key(),
+        amount,
+        timestamp: current_time,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use anchor_lang::prelude::*;
+use crate::{Presale, PresaleError};
+use crate::events::RoleChanged;
+
+/// Roles que pueden asignarse por preventa.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Gestiona los roles y liquida la preventa.
+    Owner,
+    /// Asignaciones y vesting.
+    Operator,
+    /// Lista blanca y raíz Merkle.
+    WhitelistManager,
+    /// Pausa y reanuda la venta.
+    Pauser,
+}
+
+impl Role {
+    pub fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct RoleAssignment {
+    pub presale: Pubkey,
+    pub holder: Pubkey,
+    pub roles: u8,
+    pub bump: u8,
+}
+
+impl RoleAssignment {
+    pub const SEED: &'static [u8] = b"role";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // presale
+        32 + // holder
+        1 + // roles
+        1; // bump
+
+    pub const ALL_ROLES: u8 = 0b1111;
+
+    pub fn has(&self, role: Role) -> bool {
+        self.roles & role.bit() != 0
+    }
+}
+
+#[derive(Accounts)]
+pub struct GrantRole<'info> {
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, presale.key().as_ref(), authority.key().as_ref()],
+        bump = authority_role.bump,
+        constraint = authority_role.has(Role::Owner) @ PresaleError::Unauthorized,
+    )]
+    pub authority_role: Account<'info, RoleAssignment>,
+    /// CHECK: Cuenta que recibe el rol; solo se usa como semilla
+    pub holder: UncheckedAccount<'info>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = RoleAssignment::LEN,
+        seeds = [RoleAssignment::SEED, presale.key().as_ref(), holder.key().as_ref()],
+        bump,
+    )]
+    pub holder_role: Account<'info, RoleAssignment>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeRole<'info> {
+    pub presale: Account<'info, Presale>,
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, presale.key().as_ref(), authority.key().as_ref()],
+        bump = authority_role.bump,
+        constraint = authority_role.has(Role::Owner) @ PresaleError::Unauthorized,
+    )]
+    pub authority_role: Account<'info, RoleAssignment>,
+    #[account(
+        mut,
+        seeds = [RoleAssignment::SEED, presale.key().as_ref(), holder_role.holder.as_ref()],
+        bump = holder_role.bump,
+    )]
+    pub holder_role: Account<'info, RoleAssignment>,
+}
+
+pub fn grant_role(ctx: Context<GrantRole>, role: Role) -> Result<()> {
+    let holder_role = &mut ctx.accounts.holder_role;
+    holder_role.presale = ctx.accounts.presale.key();
+    holder_role.holder = ctx.accounts.holder.key();
+    holder_role.bump = ctx.bumps.holder_role;
+    holder_role.roles |= role.bit();
+
+    emit!(RoleChanged {
+        presale: holder_role.presale,
+        holder: holder_role.holder,
+        authority: ctx.accounts.authority.key(),
+        role,
+        granted: true,
+        roles: holder_role.roles,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+pub fn revoke_role(ctx: Context<RevokeRole>, role: Role) -> Result<()> {
+    // Un owner no puede quitarse su propio rol y dejar la preventa sin dueño
+    require!(
+        !(role == Role::Owner && ctx.accounts.holder_role.holder == ctx.accounts.authority.key()),
+        PresaleError::CannotRevokeOwnOwnership
+    );
+
+    let holder_role = &mut ctx.accounts.holder_role;
+    holder_role.roles &= !role.bit();
+
+    emit!(RoleChanged {
+        presale: ctx.accounts.presale.key(),
+        holder: holder_role.holder,
+        authority: ctx.accounts.authority.key(),
+        role,
+        granted: false,
+        roles: holder_role.roles,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use super::*;
-use anchor_spl::token::Mint;
+use anchor_spl::token_interface::Mint;
 
 #[derive(Accounts)]
 pub struct Initialize<'info> {
@@ -7,7 +7,7 @@ pub struct Initialize<'info> {
     pub sale: Account<'info, Sale>,
     #[account(mut)]
     pub admin: Signer<'info>,
-    pub token_mint: Account<'info, Mint>,
+    pub token_mint: InterfaceAccount<'info, Mint>,
     pub system_program: Program<'info, System>,
 }
 
@@ -3,12 +3,26 @@ use super::*;
 pub fn pause_sale(ctx: Context<PauseSale>) -> Result<()> {
     let sale = &mut ctx.accounts.sale;
     sale.paused = true;
+
+    emit!(SalePauseChanged {
+        presale: sale.key(),
+        authority: ctx.accounts.admin.key(),
+        paused: true,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     Ok(())
 }
 
 pub fn unpause_sale(ctx: Context<UnpauseSale>) -> Result<()> {
     let sale = &mut ctx.accounts.sale;
     sale.paused = false;
+
+    emit!(SalePauseChanged {
+        presale: sale.key(),
+        authority: ctx.accounts.admin.key(),
+        paused: false,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     Ok(())
 }
 
@@ -17,6 +31,12 @@ pub struct PauseSale<'info> {
     #[account(mut)]
     pub sale: Account<'info, Presale>,
     pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Pauser) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
 }
 
 #[derive(Accounts)]
@@ -24,4 +44,10 @@ pub struct UnpauseSale<'info> {
     #[account(mut)]
     pub sale: Account<'info, Presale>,
     pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Pauser) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
 }
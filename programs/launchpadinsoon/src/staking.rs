@@ -0,0 +1,329 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{Presale, PresaleError, ProgramConfig};
+use crate::escrow::{deposit_tokens, transfer_tokens};
+use crate::events::{StakePoolUpdated, TokensStaked, TokensUnstaked};
+
+/// Nivel de staking (por ejemplo Bronze, Silver y Gold, en ese orden). Se
+/// alcanza con al menos `min_amount` tokens en stake durante `min_duration`
+/// segundos; `weight` multiplica la asignación que da cada preventa.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct StakingTier {
+    pub min_amount: u64,
+    pub min_duration: i64,
+    pub weight: u16,
+}
+
+impl StakingTier {
+    pub const LEN: usize = 8 + 8 + 2;
+    pub const MAX_TIERS: usize = 5;
+}
+
+/// Los niveles van de menor a mayor: cada uno exige más tokens, no menos tiempo
+/// y da más peso que el anterior.
+pub fn validate_staking_tiers(tiers: &[StakingTier]) -> Result<()> {
+    require!(
+        !tiers.is_empty() && tiers.len() <= StakingTier::MAX_TIERS,
+        PresaleError::InvalidStakingTiers
+    );
+    require!(
+        tiers.iter().all(|tier| tier.min_amount > 0 && tier.min_duration >= 0 && tier.weight > 0)
+            && tiers.windows(2).all(|pair| {
+                pair[0].min_amount < pair[1].min_amount
+                    && pair[0].min_duration <= pair[1].min_duration
+                    && pair[0].weight < pair[1].weight
+            }),
+        PresaleError::InvalidStakingTiers
+    );
+    Ok(())
+}
+
+/// Pool único del protocolo donde se hace stake del token del launchpad. La PDA
+/// del pool es la dueña de `stake_vault`.
+#[account]
+#[derive(Default)]
+pub struct StakePool {
+    pub stake_mint: Pubkey,
+    pub stake_vault: Pubkey,
+    /// Bloqueo mínimo tras cada stake, y después del cierre de cada preventa en
+    /// la que se usó el nivel.
+    pub lock_duration: i64,
+    pub tiers: Vec<StakingTier>,
+    pub total_staked: u64,
+    pub bump: u8,
+}
+
+impl StakePool {
+    pub const SEED: &'static [u8] = b"stake_pool";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // stake_mint
+        32 + // stake_vault
+        8 + // lock_duration
+        (4 + StakingTier::LEN * StakingTier::MAX_TIERS) + // tiers
+        8 + // total_staked
+        1; // bump
+
+    /// Nivel más alto que alcanza `user_stake` en `current_time`, con su índice.
+    pub fn tier_of(&self, user_stake: &UserStake, current_time: i64) -> Option<(usize, &StakingTier)> {
+        let staked_for = current_time.saturating_sub(user_stake.staked_since);
+        self.tiers
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, tier)| user_stake.amount >= tier.min_amount && staked_for >= tier.min_duration)
+    }
+}
+
+/// Stake de una wallet en el pool.
+#[account]
+#[derive(Default)]
+pub struct UserStake {
+    pub owner: Pubkey,
+    pub amount: u64,
+    /// Antigüedad del stake, promediada por monto en cada depósito para que
+    /// agregar tokens no herede la antigüedad del saldo previo.
+    pub staked_since: i64,
+    pub locked_until: i64,
+    pub bump: u8,
+}
+
+impl UserStake {
+    pub const SEED: &'static [u8] = b"stake";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // owner
+        8 + // amount
+        8 + // staked_since
+        8 + // locked_until
+        1; // bump
+
+    /// Bloquea el stake al menos hasta `until`.
+    pub fn extend_lock(&mut self, until: i64) {
+        self.locked_until = self.locked_until.max(until);
+    }
+}
+
+impl Presale {
+    /// Asignación que da esta preventa a un nivel de staking; `None` si la
+    /// preventa no asigna por staking.
+    pub fn tier_allocation(&self, tier: &StakingTier) -> Result<Option<u64>> {
+        if self.tokens_per_tier_weight == 0 {
+            return Ok(None);
+        }
+        let allocation = self.tokens_per_tier_weight
+            .checked_mul(tier.weight as u64)
+            .ok_or(PresaleError::CalculationError)?;
+        Ok(Some(allocation))
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeStakePool<'info> {
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = StakePool::LEN,
+        seeds = [StakePool::SEED],
+        bump,
+    )]
+    pub stake_pool: Account<'info, StakePool>,
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        constraint = stake_vault.mint == stake_mint.key() @ PresaleError::InvalidStakePool,
+        constraint = stake_vault.owner == stake_pool.key() @ PresaleError::InvalidStakePool,
+    )]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateStakePool<'info> {
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    pub admin: Signer<'info>,
+    #[account(mut, seeds = [StakePool::SEED], bump = stake_pool.bump)]
+    pub stake_pool: Account<'info, StakePool>,
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    #[account(mut, seeds = [StakePool::SEED], bump = stake_pool.bump)]
+    pub stake_pool: Account<'info, StakePool>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = UserStake::LEN,
+        seeds = [UserStake::SEED, owner.key().as_ref()],
+        bump,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    #[account(mut)]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = stake_pool.stake_vault @ PresaleError::InvalidStakePool)]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = stake_pool.stake_mint @ PresaleError::InvalidStakePool)]
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Unstake<'info> {
+    #[account(mut, seeds = [StakePool::SEED], bump = stake_pool.bump)]
+    pub stake_pool: Account<'info, StakePool>,
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [UserStake::SEED, owner.key().as_ref()],
+        bump = user_stake.bump,
+    )]
+    pub user_stake: Account<'info, UserStake>,
+    #[account(mut)]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = stake_pool.stake_vault @ PresaleError::InvalidStakePool)]
+    pub stake_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = stake_pool.stake_mint @ PresaleError::InvalidStakePool)]
+    pub stake_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+pub fn initialize_stake_pool(
+    ctx: Context<InitializeStakePool>,
+    lock_duration: i64,
+    tiers: Vec<StakingTier>,
+) -> Result<()> {
+    require!(lock_duration >= 0, PresaleError::InvalidStakingTiers);
+    validate_staking_tiers(&tiers)?;
+
+    let stake_pool = &mut ctx.accounts.stake_pool;
+    stake_pool.stake_mint = ctx.accounts.stake_mint.key();
+    stake_pool.stake_vault = ctx.accounts.stake_vault.key();
+    stake_pool.lock_duration = lock_duration;
+    stake_pool.tiers = tiers;
+    stake_pool.total_staked = 0;
+    stake_pool.bump = ctx.bumps.stake_pool;
+
+    emit!(StakePoolUpdated {
+        admin: ctx.accounts.admin.key(),
+        stake_mint: stake_pool.stake_mint,
+        lock_duration,
+        tiers: stake_pool.tiers.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Los niveles nuevos se aplican en la próxima compra de cada staker; los
+/// bloqueos ya vigentes no cambian.
+pub fn update_stake_pool(
+    ctx: Context<UpdateStakePool>,
+    lock_duration: i64,
+    tiers: Vec<StakingTier>,
+) -> Result<()> {
+    require!(lock_duration >= 0, PresaleError::InvalidStakingTiers);
+    validate_staking_tiers(&tiers)?;
+
+    let stake_pool = &mut ctx.accounts.stake_pool;
+    stake_pool.lock_duration = lock_duration;
+    stake_pool.tiers = tiers;
+
+    emit!(StakePoolUpdated {
+        admin: ctx.accounts.admin.key(),
+        stake_mint: stake_pool.stake_mint,
+        lock_duration,
+        tiers: stake_pool.tiers.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+pub fn stake<'info>(ctx: Context<'_, '_, '_, 'info, Stake<'info>>, amount: u64) -> Result<()> {
+    require!(amount > 0, PresaleError::InvalidParameters);
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // Si el mint cobra comisión de transferencia la paga el staker: el pool
+    // recibe `amount` completo
+    deposit_tokens(
+        &ctx.accounts.token_program,
+        &ctx.accounts.owner_token_account,
+        &ctx.accounts.stake_mint,
+        &mut ctx.accounts.stake_vault,
+        &ctx.accounts.owner.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+    )?;
+
+    let user_stake = &mut ctx.accounts.user_stake;
+    if user_stake.owner == Pubkey::default() {
+        user_stake.owner = ctx.accounts.owner.key();
+        user_stake.bump = ctx.bumps.user_stake;
+    }
+    let new_amount = user_stake.amount.checked_add(amount).ok_or(PresaleError::CalculationError)?;
+    let weighted_since = (user_stake.staked_since as i128 * user_stake.amount as i128
+        + current_time as i128 * amount as i128)
+        / new_amount as i128;
+    user_stake.staked_since = weighted_since as i64;
+    user_stake.amount = new_amount;
+    user_stake.extend_lock(current_time.saturating_add(ctx.accounts.stake_pool.lock_duration));
+
+    let stake_pool = &mut ctx.accounts.stake_pool;
+    stake_pool.total_staked = stake_pool.total_staked.checked_add(amount).ok_or(PresaleError::CalculationError)?;
+
+    emit!(TokensStaked {
+        owner: user_stake.owner,
+        amount,
+        staked_amount: user_stake.amount,
+        staked_since: user_stake.staked_since,
+        locked_until: user_stake.locked_until,
+        timestamp: current_time,
+    });
+    Ok(())
+}
+
+/// Retira tokens del pool una vez vencido el bloqueo; un retiro parcial conserva
+/// la antigüedad del saldo restante.
+pub fn unstake<'info>(ctx: Context<'_, '_, '_, 'info, Unstake<'info>>, amount: u64) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    let user_stake = &mut ctx.accounts.user_stake;
+    require!(amount > 0 && amount <= user_stake.amount, PresaleError::InsufficientStake);
+    require!(current_time >= user_stake.locked_until, PresaleError::StakeLocked);
+
+    user_stake.amount -= amount;
+    let stake_pool = &mut ctx.accounts.stake_pool;
+    stake_pool.total_staked = stake_pool.total_staked.saturating_sub(amount);
+
+    let pool_seeds = &[StakePool::SEED, &[stake_pool.bump]];
+    transfer_tokens(
+        &ctx.accounts.token_program,
+        &ctx.accounts.stake_vault,
+        &ctx.accounts.stake_mint,
+        &ctx.accounts.owner_token_account,
+        &stake_pool.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+        &[&pool_seeds[..]],
+    )?;
+
+    emit!(TokensUnstaked {
+        owner: user_stake.owner,
+        amount,
+        staked_amount: user_stake.amount,
+        timestamp: current_time,
+    });
+    Ok(())
+}
@@ -1,16 +1,50 @@
 use super::*;
 use crate::GlobalStats;
 use crate::presale::Presale;
+use crate::purchase::PurchaseRecord;
+use crate::config::ProgramConfig;
+use crate::staking::{StakePool, UserStake};
+use crate::lottery::{Lottery, LotteryTicket};
+use crate::liquidity::{LiquidityLock, LiquidityLockConfig};
+use crate::team::{GrantCategory, TeamGrant};
+use crate::whitelist::WhitelistEntry;
 
 #[derive(Accounts)]
 pub struct GetPresaleStats<'info> {
     pub presale: Account<'info, Presale>,
+    /// Solo tras liquidar una preventa con bloqueo de liquidez
+    #[account(seeds = [LiquidityLock::SEED, presale.key().as_ref()], bump = liquidity_lock.bump)]
+    pub liquidity_lock: Option<Account<'info, LiquidityLock>>,
 }
 
 #[derive(Accounts)]
 pub struct GetUserStats<'info> {
     pub presale: Account<'info, Presale>,
     pub user: Signer<'info>,
+    #[account(
+        seeds = [PurchaseRecord::SEED, presale.key().as_ref(), user.key().as_ref()],
+        bump = purchase_record.bump,
+    )]
+    pub purchase_record: Option<Account<'info, PurchaseRecord>>,
+    #[account(seeds = [StakePool::SEED], bump = stake_pool.bump)]
+    pub stake_pool: Option<Account<'info, StakePool>>,
+    #[account(
+        seeds = [UserStake::SEED, user.key().as_ref()],
+        bump = user_stake.bump,
+    )]
+    pub user_stake: Option<Account<'info, UserStake>>,
+    #[account(seeds = [Lottery::SEED, presale.key().as_ref()], bump = lottery.bump)]
+    pub lottery: Option<Account<'info, Lottery>>,
+    #[account(
+        seeds = [LotteryTicket::SEED, presale.key().as_ref(), user.key().as_ref()],
+        bump = lottery_ticket.bump,
+    )]
+    pub lottery_ticket: Option<Account<'info, LotteryTicket>>,
+    #[account(
+        seeds = [WhitelistEntry::SEED, presale.key().as_ref(), user.key().as_ref()],
+        bump = whitelist_entry.bump,
+    )]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
 }
 
 #[derive(Accounts)]
@@ -18,6 +52,12 @@ pub struct UpdateGlobalStats<'info> {
     #[account(mut)]
     pub global_stats: Account<'info, GlobalStats>,
     pub admin: Signer<'info>,
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
     pub presale: Account<'info, Presale>,
 }
 
@@ -25,6 +65,7 @@ pub struct UpdateGlobalStats<'info> {
 pub struct PresaleStats {
     pub id: u64,
     pub raise_token: Pubkey,
+    pub raise_mode: RaiseMode,
     pub sale_token: Pubkey,
     pub total_tokens: u64,
     pub tokens_sold: u64,
@@ -38,6 +79,35 @@ pub struct PresaleStats {
     pub time_remaining: i64,
     pub percentage_sold: f64,
     pub percentage_raised: f64,
+    pub status: PresaleStatus,
+    pub soft_cap_reached: bool,
+    pub current_tier: Option<u8>,
+    pub current_price: u64,
+    pub clearing_price: u64,
+    pub sale_mode: SaleMode,
+    pub oversubscribed: bool,
+    pub commission_paid: u64,
+    pub raise_fee_bps: u16,
+    pub referral_bps: u16,
+    pub referred_raised: u64,
+    pub referral_rewards: u64,
+    pub referral_rewards_claimed: u64,
+    pub current_phase: Option<u8>,
+    /// Configuración y progreso (tokens vendidos y recaudado) de cada fase.
+    pub phases: Vec<SalePhase>,
+    pub tokens_per_tier_weight: u64,
+    pub liquidity_lock: LiquidityLockConfig,
+    /// Estado del bloqueo de liquidez; todo en 0 hasta que se liquide la preventa.
+    pub liquidity_unlock_time: i64,
+    pub locked_sale_tokens: u64,
+    pub locked_proceeds: u64,
+    pub lp_mint: Option<Pubkey>,
+    pub locked_lp_tokens: u64,
+    pub team_grants: u32,
+    pub team_granted: u64,
+    pub team_claimed: u64,
+    pub team_revoked: u64,
+    pub transfer_requires_approval: bool,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
@@ -47,6 +117,61 @@ pub struct UserStats {
     pub vesting_amount: u64,
     pub vesting_release_time: i64,
     pub claimed: bool,
+    pub claimed_amount: u64,
+    /// Lo recibido tras la comisión de transferencia del mint, si la hay.
+    pub received_amount: u64,
+    pub claimable_amount: u64,
+    pub allocated_tokens: u64,
+    pub refund_due: u64,
+    pub has_explicit_allocation: bool,
+    pub min_purchase: u64,
+    pub max_purchase_per_tx: u64,
+    /// Tope de tokens de la wallet; `None` si no hay límite.
+    pub wallet_limit: Option<u64>,
+    /// Lo que aún puede comprar según `wallet_limit`; `None` si no hay límite.
+    pub remaining_allowance: Option<u64>,
+    /// Tokens comprados en cada fase de la preventa.
+    pub phase_purchases: Vec<u64>,
+    pub staked_amount: u64,
+    pub stake_locked_until: i64,
+    /// Nivel de staking vigente; `None` si no alcanza ninguno.
+    pub stake_tier: Option<u8>,
+    /// Asignación que da esta preventa al nivel de staking actual.
+    pub tier_allocation: Option<u64>,
+    /// Número de boleto en el sorteo de la preventa, si se registró.
+    pub lottery_ticket: Option<u32>,
+    pub lottery_drawn: bool,
+    /// Asignación ganada en el sorteo; `None` si no ganó o aún no se sorteó.
+    pub lottery_allocation: Option<u64>,
+}
+
+/// Consulta pública: no exige firma del beneficiario.
+#[derive(Accounts)]
+pub struct GetTeamGrantStats<'info> {
+    pub presale: Account<'info, Presale>,
+    #[account(
+        seeds = [TeamGrant::SEED, presale.key().as_ref(), team_grant.beneficiary.as_ref()],
+        bump = team_grant.bump,
+    )]
+    pub team_grant: Account<'info, TeamGrant>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct TeamGrantStats {
+    pub beneficiary: Pubkey,
+    pub category: GrantCategory,
+    pub amount: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub vested_amount: u64,
+    pub claimed_amount: u64,
+    pub claimable_amount: u64,
+    /// Lo que aún no se desbloquea.
+    pub locked_amount: u64,
+    pub revocable: bool,
+    pub revoked: bool,
+    /// Lo devuelto a tesorería si se revocó.
+    pub revoked_amount: u64,
 }
 
 #[derive(Accounts)]
@@ -60,6 +185,12 @@ pub struct InitializeGlobalStats<'info> {
     pub global_stats: Account<'info, GlobalStats>,
     #[account(mut)]
     pub admin: Signer<'info>,
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        has_one = admin @ PresaleError::Unauthorized,
+    )]
+    pub config: Account<'info, ProgramConfig>,
     pub system_program: Program<'info, System>,
 }
 
@@ -69,6 +200,14 @@ pub fn initialize_global_stats(ctx: Context<InitializeGlobalStats>) -> Result<()
     global_stats.total_raised = 0;
     global_stats.total_investors = 0;
     global_stats.presales = Vec::new();
+
+    emit!(GlobalStatsUpdated {
+        presale_id: None,
+        total_presales: 0,
+        total_raised: 0,
+        total_investors: 0,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     Ok(())
 }
 
@@ -96,6 +235,13 @@ pub fn update_global_stats(ctx: Context<UpdateGlobalStats>) -> Result<()> {
     global_stats.total_raised = global_stats.presales.iter().map(|p| p.total_raised).sum();
     global_stats.total_investors = global_stats.presales.iter().map(|p| p.total_investors).sum(); // Cambiar a u64 si es necesario
 
+    emit!(GlobalStatsUpdated {
+        presale_id: Some(presale.id),
+        total_presales: global_stats.total_presales,
+        total_raised: global_stats.total_raised,
+        total_investors: global_stats.total_investors,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     Ok(())
 }
 
@@ -105,9 +251,12 @@ pub fn get_global_stats(ctx: Context<GetGlobalStats>) -> Result<GlobalStats> {
 
 pub fn get_presale_stats(ctx: Context<GetPresaleStats>) -> Result<PresaleStats> {
     let presale = &ctx.accounts.presale;
+    let liquidity_lock = ctx.accounts.liquidity_lock.as_ref();
     let current_time = Clock::get()?.unix_timestamp;
 
-    let is_active = current_time >= presale.start_time && current_time <= presale.end_time;
+    let is_active = presale.status == PresaleStatus::Active
+        && current_time >= presale.start_time
+        && current_time <= presale.end_time;
     let time_remaining = if is_active {
         presale.end_time - current_time
     } else {
@@ -126,9 +275,20 @@ pub fn get_presale_stats(ctx: Context<GetPresaleStats>) -> Result<PresaleStats>
         0.0
     };
 
+    // Reflejar el estado fallido aunque nadie haya llamado aún a refund
+    let status = if presale.status == PresaleStatus::Active
+        && current_time > presale.end_time
+        && !presale.soft_cap_reached()
+    {
+        PresaleStatus::Failed
+    } else {
+        presale.status
+    };
+
     Ok(PresaleStats {
         id: presale.id,
         raise_token: presale.raise_token,
+        raise_mode: presale.raise_mode,
         sale_token: presale.sale_token,
         total_tokens: presale.total_tokens,
         tokens_sold: presale.tokens_sold,
@@ -142,33 +302,87 @@ pub fn get_presale_stats(ctx: Context<GetPresaleStats>) -> Result<PresaleStats>
         time_remaining,
         percentage_sold,
         percentage_raised,
+        status,
+        soft_cap_reached: presale.soft_cap_reached(),
+        current_tier: presale.current_tier().map(|(index, _)| index as u8),
+        current_price: presale.current_price(current_time),
+        clearing_price: presale.clearing_price,
+        sale_mode: presale.sale_mode,
+        oversubscribed: presale.is_oversubscribed(),
+        commission_paid: presale.commission_paid,
+        raise_fee_bps: presale.raise_fee_bps,
+        referral_bps: presale.referral_bps,
+        referred_raised: presale.referred_raised,
+        referral_rewards: presale.referral_rewards,
+        referral_rewards_claimed: presale.referral_rewards_claimed,
+        current_phase: presale.active_phase(current_time).map(|(index, _)| index as u8),
+        phases: presale.phases.clone(),
+        tokens_per_tier_weight: presale.tokens_per_tier_weight,
+        liquidity_lock: presale.liquidity_lock,
+        liquidity_unlock_time: liquidity_lock.map(|lock| lock.unlock_time).unwrap_or(0),
+        locked_sale_tokens: liquidity_lock.map(|lock| lock.locked_sale_tokens).unwrap_or(0),
+        locked_proceeds: liquidity_lock.map(|lock| lock.locked_proceeds).unwrap_or(0),
+        lp_mint: liquidity_lock
+            .map(|lock| lock.lp_mint)
+            .filter(|lp_mint| *lp_mint != Pubkey::default()),
+        locked_lp_tokens: liquidity_lock.map(|lock| lock.locked_lp_tokens).unwrap_or(0),
+        team_grants: presale.team_grants,
+        team_granted: presale.team_granted,
+        team_claimed: presale.team_claimed,
+        team_revoked: presale.team_revoked,
+        transfer_requires_approval: presale.transfer_requires_approval,
     })
 }
 
 pub fn get_user_stats(ctx: Context<GetUserStats>) -> Result<UserStats> {
     let presale = &ctx.accounts.presale;
-    let user_key = ctx.accounts.user.key();
 
-    let allocation = presale.allocations.iter()
-        .find(|(pubkey, _)| pubkey == &user_key)
-        .map(|(_, allocation)| *allocation)
-        .unwrap_or(0);
+    let current_time = Clock::get()?.unix_timestamp;
+    let user_stake = ctx.accounts.user_stake.as_ref();
+    let stake_tier = match (ctx.accounts.stake_pool.as_ref(), user_stake) {
+        (Some(stake_pool), Some(user_stake)) => stake_pool.tier_of(user_stake, current_time),
+        _ => None,
+    };
+    let tier_allocation = match stake_tier {
+        Some((_, tier)) => presale.tier_allocation(tier)?,
+        None => None,
+    };
 
-    let tokens_purchased = presale.buyer_purchases.iter()
-        .find(|(pubkey, _)| pubkey == &user_key)
-        .map(|(_, amount)| *amount)
-        .unwrap_or(0);
+    let lottery = ctx.accounts.lottery.as_ref();
+    let lottery_ticket = ctx.accounts.lottery_ticket.as_ref();
+    let lottery_allocation = match (lottery, lottery_ticket) {
+        (Some(lottery), Some(ticket)) => lottery.allocation_for(ticket),
+        _ => None,
+    };
 
-    let vesting_info = presale.vestings.iter()
-        .find(|(pubkey, _)| pubkey == &user_key)
-        .map(|(_, info)| info);
+    let explicit_allocation = ctx.accounts.whitelist_entry.as_ref().and_then(|entry| entry.allocation);
+    let allocation = explicit_allocation.unwrap_or(0);
+    let wallet_limit = presale.wallet_limit(explicit_allocation.or(lottery_allocation).or(tier_allocation));
+
+    let purchase_record = ctx.accounts.purchase_record.as_ref();
+    let tokens_purchased = purchase_record.map(|record| record.tokens_purchased).unwrap_or(0);
+    let (allocated_tokens, refund_due) = match purchase_record {
+        Some(record) => (record.allocated_tokens(presale)?, record.refund_due(presale)?),
+        None => (0, 0),
+    };
+
+    let vesting_info = purchase_record
+        .map(|record| &record.vesting)
+        .filter(|info| info.amount > 0);
 
     let vesting_amount = vesting_info.map(|info| info.amount).unwrap_or(0);
     let vesting_release_time = vesting_info.map(|info| info.release_time).unwrap_or(0);
-    let claimed = presale.claims.iter()
-        .find(|(pubkey, _)| pubkey == &user_key)
-        .map(|(_, claimed)| *claimed)
-        .unwrap_or(false);
+    let claimed_amount = vesting_info.map(|info| info.claimed_amount).unwrap_or(0);
+    let claimed = vesting_amount > 0 && claimed_amount >= vesting_amount;
+
+    let claimable_amount = match vesting_info {
+        Some(info) => {
+            presale.vesting_schedule
+                .vested_amount(info.amount, info.release_time, presale.vesting_end_time, current_time)?
+                .saturating_sub(info.claimed_amount)
+        }
+        None => 0,
+    };
 
     Ok(UserStats {
         allocation,
@@ -176,5 +390,47 @@ pub fn get_user_stats(ctx: Context<GetUserStats>) -> Result<UserStats> {
         vesting_amount,
         vesting_release_time,
         claimed,
+        claimed_amount,
+        received_amount: vesting_info.map(|info| info.received_amount).unwrap_or(0),
+        claimable_amount,
+        allocated_tokens,
+        refund_due,
+        has_explicit_allocation: explicit_allocation.is_some(),
+        min_purchase: presale.purchase_limits.min_purchase,
+        max_purchase_per_tx: presale.purchase_limits.max_purchase_per_tx,
+        wallet_limit,
+        remaining_allowance: wallet_limit.map(|limit| limit.saturating_sub(tokens_purchased)),
+        phase_purchases: presale.phases.iter()
+            .enumerate()
+            .map(|(index, _)| purchase_record.map(|record| record.phase_purchases[index]).unwrap_or(0))
+            .collect(),
+        staked_amount: user_stake.map(|user_stake| user_stake.amount).unwrap_or(0),
+        stake_locked_until: user_stake.map(|user_stake| user_stake.locked_until).unwrap_or(0),
+        stake_tier: stake_tier.map(|(index, _)| index as u8),
+        tier_allocation,
+        lottery_ticket: lottery_ticket.map(|ticket| ticket.index),
+        lottery_drawn: lottery.map(|lottery| lottery.drawn).unwrap_or(false),
+        lottery_allocation,
+    })
+}
+
+pub fn get_team_grant_stats(ctx: Context<GetTeamGrantStats>) -> Result<TeamGrantStats> {
+    let team_grant = &ctx.accounts.team_grant;
+    let current_time = Clock::get()?.unix_timestamp;
+    let vested_amount = team_grant.vested_amount(current_time)?;
+
+    Ok(TeamGrantStats {
+        beneficiary: team_grant.beneficiary,
+        category: team_grant.category,
+        amount: team_grant.amount,
+        start_time: team_grant.start_time,
+        end_time: team_grant.end_time,
+        vested_amount,
+        claimed_amount: team_grant.claimed_amount,
+        claimable_amount: vested_amount.saturating_sub(team_grant.claimed_amount),
+        locked_amount: team_grant.amount.saturating_sub(vested_amount),
+        revocable: team_grant.revocable,
+        revoked: team_grant.is_revoked(),
+        revoked_amount: team_grant.revoked_amount,
     })
 }
@@ -0,0 +1,321 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use crate::{Presale, PresaleError, ProgramConfig, Role, RoleAssignment};
+use crate::escrow::{deposit_tokens, transfer_tokens};
+use crate::events::{TeamGrantClaimed, TeamGrantCreated, TeamGrantRevoked};
+use crate::vesting::VestingSchedule;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GrantCategory {
+    #[default]
+    Team,
+    Advisor,
+    Other,
+}
+
+/// Asignación de tokens del creador a un miembro del equipo o asesor, con su
+/// propio calendario. Es independiente del vesting de los compradores: los
+/// tokens quedan en `vault`, una cuenta cuyo dueño es esta PDA, así cualquiera
+/// puede verificar qué parte del suministro se desbloquea y cuándo.
+///
+/// La PDA se deriva de la preventa y del beneficiario, así que cada wallet
+/// tiene como máximo una asignación por preventa; `create_team_grant` falla si
+/// ya existe. Para otro calendario hay que usar otra wallet.
+#[account]
+#[derive(Default)]
+pub struct TeamGrant {
+    pub presale: Pubkey,
+    pub beneficiary: Pubkey,
+    pub category: GrantCategory,
+    pub vault: Pubkey,
+    pub amount: u64,
+    pub claimed_amount: u64,
+    /// TGE del calendario; el desbloqueo es completo en `end_time`.
+    pub start_time: i64,
+    pub end_time: i64,
+    pub schedule: VestingSchedule,
+    /// Se fija al crear la asignación; el vesting de los compradores nunca es revocable.
+    pub revocable: bool,
+    /// Cuenta del token de venta que recibe lo no desbloqueado al revocar.
+    pub treasury: Pubkey,
+    /// 0 mientras no se haya revocado.
+    pub revoked_at: i64,
+    pub revoked_amount: u64,
+    pub bump: u8,
+}
+
+impl TeamGrant {
+    pub const SEED: &'static [u8] = b"team_grant";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // presale
+        32 + // beneficiary
+        1 + // category
+        32 + // vault
+        8 + // amount
+        8 + // claimed_amount
+        8 + // start_time
+        8 + // end_time
+        VestingSchedule::LEN + // schedule
+        1 + // revocable
+        32 + // treasury
+        8 + // revoked_at
+        8 + // revoked_amount
+        1; // bump
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at != 0
+    }
+
+    /// Tras revocar, `amount` queda en lo desbloqueado hasta ese momento.
+    pub fn vested_amount(&self, current_time: i64) -> Result<u64> {
+        if self.is_revoked() {
+            return Ok(self.amount);
+        }
+        self.schedule.vested_amount(self.amount, self.start_time, self.end_time, current_time)
+    }
+
+    pub fn claimable_amount(&self, current_time: i64) -> Result<u64> {
+        Ok(self.vested_amount(current_time)?.saturating_sub(self.claimed_amount))
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(beneficiary: Pubkey)]
+pub struct CreateTeamGrant<'info> {
+    #[account(mut, has_one = creator)]
+    pub presale: Account<'info, Presale>,
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(
+        init,
+        payer = creator,
+        space = TeamGrant::LEN,
+        seeds = [TeamGrant::SEED, presale.key().as_ref(), beneficiary.as_ref()],
+        bump,
+    )]
+    pub team_grant: Account<'info, TeamGrant>,
+    #[account(mut)]
+    pub creator_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// Cuenta del token de venta cuyo dueño es `team_grant`
+    #[account(mut)]
+    pub grant_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = presale.sale_token @ PresaleError::InvalidSaleMint)]
+    pub sale_mint: InterfaceAccount<'info, Mint>,
+    /// Solo en asignaciones revocables: recibe lo no desbloqueado al revocar
+    pub treasury: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimTeamGrant<'info> {
+    #[account(mut)]
+    pub presale: Account<'info, Presale>,
+    pub beneficiary: Signer<'info>,
+    #[account(
+        seeds = [ProgramConfig::SEED],
+        bump = config.bump,
+        constraint = !config.circuit_breaker.claims_paused @ PresaleError::ProtocolPaused,
+    )]
+    pub config: Account<'info, ProgramConfig>,
+    #[account(
+        mut,
+        seeds = [TeamGrant::SEED, presale.key().as_ref(), beneficiary.key().as_ref()],
+        bump = team_grant.bump,
+    )]
+    pub team_grant: Account<'info, TeamGrant>,
+    #[account(mut, address = team_grant.vault @ PresaleError::InvalidTeamGrant)]
+    pub grant_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub beneficiary_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = presale.sale_token @ PresaleError::InvalidSaleMint)]
+    pub sale_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeTeamGrant<'info> {
+    #[account(mut)]
+    pub presale: Account<'info, Presale>,
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, presale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Operator) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+    #[account(
+        mut,
+        seeds = [TeamGrant::SEED, presale.key().as_ref(), team_grant.beneficiary.as_ref()],
+        bump = team_grant.bump,
+    )]
+    pub team_grant: Account<'info, TeamGrant>,
+    #[account(mut, address = team_grant.vault @ PresaleError::InvalidTeamGrant)]
+    pub grant_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = team_grant.treasury @ PresaleError::InvalidTeamGrant)]
+    pub treasury: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = presale.sale_token @ PresaleError::InvalidSaleMint)]
+    pub sale_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+/// Crea y fondea una asignación de equipo con tokens del creador. Puede ir en la
+/// misma transacción que `initialize_presale` o en cualquier momento posterior.
+pub fn create_team_grant<'info>(
+    ctx: Context<'_, '_, '_, 'info, CreateTeamGrant<'info>>,
+    beneficiary: Pubkey,
+    category: GrantCategory,
+    amount: u64,
+    start_time: i64,
+    end_time: i64,
+    schedule: VestingSchedule,
+    revocable: bool,
+) -> Result<()> {
+    schedule.validate()?;
+    require!(
+        amount > 0 && start_time < end_time && beneficiary != Pubkey::default(),
+        PresaleError::InvalidTeamGrant
+    );
+    let team_grant_key = ctx.accounts.team_grant.key();
+    require!(
+        ctx.accounts.grant_vault.owner == team_grant_key
+            && ctx.accounts.grant_vault.mint == ctx.accounts.presale.sale_token,
+        PresaleError::InvalidTeamGrant
+    );
+    let treasury = if revocable {
+        let treasury = ctx.accounts.treasury.as_ref().ok_or(PresaleError::InvalidTeamGrant)?;
+        require!(treasury.mint == ctx.accounts.presale.sale_token, PresaleError::InvalidTeamGrant);
+        treasury.key()
+    } else {
+        Pubkey::default()
+    };
+
+    // Si el mint cobra comisión de transferencia la paga el creador: la bóveda
+    // de la asignación recibe `amount` completo
+    deposit_tokens(
+        &ctx.accounts.token_program,
+        &ctx.accounts.creator_token_account,
+        &ctx.accounts.sale_mint,
+        &mut ctx.accounts.grant_vault,
+        &ctx.accounts.creator.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+    )?;
+
+    let team_grant = &mut ctx.accounts.team_grant;
+    team_grant.presale = ctx.accounts.presale.key();
+    team_grant.beneficiary = beneficiary;
+    team_grant.category = category;
+    team_grant.vault = ctx.accounts.grant_vault.key();
+    team_grant.amount = amount;
+    team_grant.claimed_amount = 0;
+    team_grant.start_time = start_time;
+    team_grant.end_time = end_time;
+    team_grant.schedule = schedule;
+    team_grant.revocable = revocable;
+    team_grant.treasury = treasury;
+    team_grant.revoked_at = 0;
+    team_grant.revoked_amount = 0;
+    team_grant.bump = ctx.bumps.team_grant;
+
+    let presale = &mut ctx.accounts.presale;
+    presale.team_grants = presale.team_grants.checked_add(1).ok_or(PresaleError::CalculationError)?;
+    presale.team_granted = presale.team_granted.checked_add(amount).ok_or(PresaleError::CalculationError)?;
+
+    emit!(TeamGrantCreated {
+        presale: presale.key(),
+        beneficiary,
+        category,
+        amount,
+        start_time,
+        end_time,
+        schedule: team_grant.schedule.clone(),
+        revocable,
+        treasury,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+pub fn claim_team_grant<'info>(ctx: Context<'_, '_, '_, 'info, ClaimTeamGrant<'info>>) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    let team_grant = &ctx.accounts.team_grant;
+    let amount = team_grant.claimable_amount(current_time)?;
+    require!(amount > 0, PresaleError::NoTokensToClaim);
+
+    let presale_key = ctx.accounts.presale.key();
+    let beneficiary_key = ctx.accounts.beneficiary.key();
+    let grant_seeds = &[TeamGrant::SEED, presale_key.as_ref(), beneficiary_key.as_ref(), &[team_grant.bump]];
+    transfer_tokens(
+        &ctx.accounts.token_program,
+        &ctx.accounts.grant_vault,
+        &ctx.accounts.sale_mint,
+        &ctx.accounts.beneficiary_token_account,
+        &team_grant.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+        &[&grant_seeds[..]],
+    )?;
+
+    let team_grant = &mut ctx.accounts.team_grant;
+    team_grant.claimed_amount = team_grant.claimed_amount.checked_add(amount).ok_or(PresaleError::CalculationError)?;
+    let presale = &mut ctx.accounts.presale;
+    presale.team_claimed = presale.team_claimed.checked_add(amount).ok_or(PresaleError::CalculationError)?;
+
+    emit!(TeamGrantClaimed {
+        presale: presale_key,
+        beneficiary: beneficiary_key,
+        amount,
+        claimed_amount: team_grant.claimed_amount,
+        grant_amount: team_grant.amount,
+        timestamp: current_time,
+    });
+    Ok(())
+}
+
+/// Revoca una asignación revocable: lo desbloqueado hasta ahora sigue siendo del
+/// beneficiario y el resto vuelve a la tesorería fijada al crearla.
+pub fn revoke_team_grant<'info>(ctx: Context<'_, '_, '_, 'info, RevokeTeamGrant<'info>>) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    let team_grant = &ctx.accounts.team_grant;
+    require!(team_grant.revocable, PresaleError::GrantNotRevocable);
+    require!(!team_grant.is_revoked(), PresaleError::GrantAlreadyRevoked);
+
+    let vested_amount = team_grant.vested_amount(current_time)?;
+    let unvested_amount = team_grant.amount - vested_amount;
+
+    let presale_key = ctx.accounts.presale.key();
+    let beneficiary = team_grant.beneficiary;
+    if unvested_amount > 0 {
+        let grant_seeds = &[TeamGrant::SEED, presale_key.as_ref(), beneficiary.as_ref(), &[team_grant.bump]];
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.grant_vault,
+            &ctx.accounts.sale_mint,
+            &ctx.accounts.treasury,
+            &team_grant.to_account_info(),
+            ctx.remaining_accounts,
+            unvested_amount,
+            &[&grant_seeds[..]],
+        )?;
+    }
+
+    let team_grant = &mut ctx.accounts.team_grant;
+    team_grant.amount = vested_amount;
+    team_grant.revoked_at = current_time;
+    team_grant.revoked_amount = unvested_amount;
+    let presale = &mut ctx.accounts.presale;
+    presale.team_revoked = presale.team_revoked.checked_add(unvested_amount).ok_or(PresaleError::CalculationError)?;
+
+    emit!(TeamGrantRevoked {
+        presale: presale_key,
+        beneficiary,
+        authority: ctx.accounts.admin.key(),
+        vested_amount,
+        revoked_amount: unvested_amount,
+        treasury: team_grant.treasury,
+        timestamp: current_time,
+    });
+    Ok(())
+}
@@ -1,12 +1,27 @@
 use anchor_lang::prelude::*;
-use crate::{Presale, PresaleError, GlobalStats};
+use crate::{Presale, PresaleError, PresaleStatus, SaleMode, GlobalStats, PurchaseRecord, Role, RoleAssignment};
+use crate::events::VestingCreated;
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
 
 #[derive(Accounts)]
 pub struct CreateVesting<'info> {
     #[account(mut)]
     pub sale: Account<'info, Presale>,
     pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::Operator) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
     pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [PurchaseRecord::SEED, sale.key().as_ref(), user.key().as_ref()],
+        bump = purchase_record.bump,
+    )]
+    pub purchase_record: Account<'info, PurchaseRecord>,
     #[account(mut)]
     pub global_stats: Account<'info, GlobalStats>,
 }
@@ -15,11 +30,84 @@ pub struct CreateVesting<'info> {
 pub struct VestingInfo {
     pub amount: u64,
     pub release_time: i64,
-    pub claimed: bool,
+    /// Tokens que salieron del token_vault hacia el comprador.
+    pub claimed_amount: u64,
+    /// Lo que el comprador recibió de verdad: menor que `claimed_amount` si el
+    /// mint cobra comisión de transferencia.
+    pub received_amount: u64,
 }
 
 impl VestingInfo {
-    pub const LEN: usize = 8 + 8 + 1; // u64 + i64 + bool
+    pub const LEN: usize = 8 + 8 + 8 + 8; // u64 + i64 + u64 + u64
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VestingKind {
+    /// Desbloqueo continuo desde el fin del cliff hasta `vesting_end_time`.
+    #[default]
+    Linear,
+    /// Desbloqueo en tramos iguales cada `period_duration` segundos (p. ej. mensual).
+    Periodic,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct VestingSchedule {
+    pub tge_unlock_bps: u16,
+    pub cliff_duration: i64,
+    pub kind: VestingKind,
+    pub period_duration: i64,
+}
+
+impl VestingSchedule {
+    pub const LEN: usize = 2 + 8 + 1 + 8; // u16 + i64 + enum + i64
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.tge_unlock_bps as u64 <= BPS_DENOMINATOR && self.cliff_duration >= 0,
+            PresaleError::InvalidVestingSchedule
+        );
+        if self.kind == VestingKind::Periodic {
+            require!(self.period_duration > 0, PresaleError::InvalidVestingSchedule);
+        }
+        Ok(())
+    }
+
+    /// Cantidad desbloqueada de `total` en `now`, con el TGE en `start` y el
+    /// desbloqueo completo en `end`.
+    pub fn vested_amount(&self, total: u64, start: i64, end: i64, now: i64) -> Result<u64> {
+        if now < start {
+            return Ok(0);
+        }
+        if now >= end {
+            return Ok(total);
+        }
+
+        let tge_amount = (total as u128)
+            .checked_mul(self.tge_unlock_bps as u128)
+            .ok_or(PresaleError::CalculationError)?
+            / BPS_DENOMINATOR as u128;
+        let cliff_end = start.checked_add(self.cliff_duration).ok_or(PresaleError::CalculationError)?;
+        if now < cliff_end || end <= cliff_end {
+            return Ok(tge_amount as u64);
+        }
+
+        let (elapsed, duration) = match self.kind {
+            VestingKind::Linear => ((now - cliff_end) as u128, (end - cliff_end) as u128),
+            VestingKind::Periodic => {
+                let period = self.period_duration;
+                let total_periods = (end - cliff_end + period - 1) / period;
+                (((now - cliff_end) / period) as u128, total_periods as u128)
+            }
+        };
+
+        let remaining = total as u128 - tge_amount;
+        let linear_amount = remaining
+            .checked_mul(elapsed)
+            .ok_or(PresaleError::CalculationError)?
+            / duration;
+
+        Ok((tge_amount + linear_amount) as u64)
+    }
 }
 
 pub fn create_vesting(ctx: Context<CreateVesting>, amount: u64, release_time: i64) -> Result<()> {
@@ -27,30 +115,29 @@ pub fn create_vesting(ctx: Context<CreateVesting>, amount: u64, release_time: i6
     let user_key = ctx.accounts.user.key();
     let global_stats = &mut ctx.accounts.global_stats;
 
-    require!(
-        !sale.vestings.iter().any(|(pubkey, _)| pubkey == &user_key),
-        PresaleError::VestingAlreadyExists
-    );
+    let purchase_record = &mut ctx.accounts.purchase_record;
+    require!(!purchase_record.has_vesting(), PresaleError::VestingAlreadyExists);
 
+    // En modo overflow la asignación solo es definitiva tras liquidar la preventa
     require!(
-        sale.vestings.len() < sale.max_entries as usize,
-        PresaleError::InsufficientSpace
+        sale.status == PresaleStatus::Finalized || !matches!(sale.sale_mode, SaleMode::Overflow { .. }),
+        PresaleError::AllocationNotFinal
     );
 
-    // Verificar si el usuario ha comprado tokens
+    // Verificar que el usuario ha comprado tokens y que el vesting no excede su compra
+    require!(purchase_record.tokens_purchased > 0, PresaleError::NoPurchaseFound);
+    require!(amount > 0, PresaleError::NoTokensToClaim);
     require!(
-        sale.buyer_purchases.iter().any(|(pubkey, _)| pubkey == &user_key),
-        PresaleError::NoPurchaseFound
+        amount <= purchase_record.allocated_tokens(sale)?,
+        PresaleError::VestingExceedsPurchase
     );
 
-    sale.vestings.push((
-        user_key,
-        VestingInfo {
-            amount,
-            release_time,
-            claimed: false,
-        },
-    ));
+    purchase_record.vesting = VestingInfo {
+        amount,
+        release_time,
+        claimed_amount: 0,
+        received_amount: 0,
+    };
 
     // Actualizar estadísticas globales
     if let Some(presale_info) = global_stats.presales.iter_mut().find(|p| p.id == sale.id) {
@@ -58,5 +145,86 @@ pub fn create_vesting(ctx: Context<CreateVesting>, amount: u64, release_time: i6
     }
     global_stats.total_investors = global_stats.total_investors.saturating_add(1);
 
+    emit!(VestingCreated {
+        presale: sale.key(),
+        user: user_key,
+        amount,
+        release_time,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: i64 = 1_000;
+    const END: i64 = 1_000 + 1_000;
+
+    fn schedule(tge_unlock_bps: u16, cliff_duration: i64, kind: VestingKind, period_duration: i64) -> VestingSchedule {
+        VestingSchedule { tge_unlock_bps, cliff_duration, kind, period_duration }
+    }
+
+    #[test]
+    fn nothing_vests_before_start() {
+        let schedule = schedule(2_000, 0, VestingKind::Linear, 0);
+        assert_eq!(schedule.vested_amount(1_000, START, END, START - 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn only_tge_unlock_vests_before_cliff() {
+        let schedule = schedule(2_000, 200, VestingKind::Linear, 0);
+        assert_eq!(schedule.vested_amount(1_000, START, END, START).unwrap(), 200);
+        assert_eq!(schedule.vested_amount(1_000, START, END, START + 199).unwrap(), 200);
+        // Sin TGE no se desbloquea nada hasta el fin del cliff
+        let schedule = VestingSchedule { tge_unlock_bps: 0, ..schedule };
+        assert_eq!(schedule.vested_amount(1_000, START, END, START + 199).unwrap(), 0);
+    }
+
+    #[test]
+    fn linear_vesting_after_cliff() {
+        // 20% en el TGE; el 80% restante se libera entre el fin del cliff y END
+        let schedule = schedule(2_000, 200, VestingKind::Linear, 0);
+        assert_eq!(schedule.vested_amount(1_000, START, END, START + 200).unwrap(), 200);
+        assert_eq!(schedule.vested_amount(1_000, START, END, START + 600).unwrap(), 600);
+        assert_eq!(schedule.vested_amount(1_000, START, END, START + 999).unwrap(), 999);
+    }
+
+    #[test]
+    fn linear_vesting_rounds_down() {
+        let schedule = schedule(0, 0, VestingKind::Linear, 0);
+        assert_eq!(schedule.vested_amount(10, START, END, START + 1).unwrap(), 0);
+        assert_eq!(schedule.vested_amount(10, START, END, START + 150).unwrap(), 1);
+    }
+
+    #[test]
+    fn periodic_vesting_unlocks_in_steps() {
+        // Cuatro tramos de 250 segundos sin TGE
+        let schedule = schedule(0, 0, VestingKind::Periodic, 250);
+        assert_eq!(schedule.vested_amount(1_000, START, END, START + 249).unwrap(), 0);
+        assert_eq!(schedule.vested_amount(1_000, START, END, START + 250).unwrap(), 250);
+        assert_eq!(schedule.vested_amount(1_000, START, END, START + 749).unwrap(), 500);
+    }
+
+    #[test]
+    fn everything_vests_at_end() {
+        for schedule in [
+            schedule(2_000, 200, VestingKind::Linear, 0),
+            schedule(0, 0, VestingKind::Periodic, 300),
+            // Un cliff más largo que el calendario no retiene nada al final
+            schedule(0, 5_000, VestingKind::Linear, 0),
+        ] {
+            assert_eq!(schedule.vested_amount(1_000, START, END, END).unwrap(), 1_000);
+            assert_eq!(schedule.vested_amount(1_000, START, END, END + 1).unwrap(), 1_000);
+        }
+    }
+
+    #[test]
+    fn validate_rejects_invalid_schedules() {
+        assert!(schedule(10_001, 0, VestingKind::Linear, 0).validate().is_err());
+        assert!(schedule(0, -1, VestingKind::Linear, 0).validate().is_err());
+        assert!(schedule(0, 0, VestingKind::Periodic, 0).validate().is_err());
+        assert!(schedule(10_000, 0, VestingKind::Periodic, 1).validate().is_ok());
+    }
+}
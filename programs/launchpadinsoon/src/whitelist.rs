@@ -1,16 +1,447 @@
 use super::*;
+use anchor_lang::solana_program::keccak;
+
+/// Máximo de cambios por llamada a `update_whitelist_batch`, para no exceder el
+/// presupuesto de cómputo de una transacción.
+pub const MAX_WHITELIST_BATCH: usize = 20;
+
+/// Cambio de una entrada en `update_whitelist_batch`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum WhitelistChange {
+    /// Agrega a `user`; con `allocation` fija además su asignación explícita.
+    Add { user: Pubkey, allocation: Option<u64> },
+    /// Cambia la asignación explícita de `user`; `None` la quita. Si ya compró,
+    /// su nuevo límite no puede quedar por debajo de lo comprado.
+    Update { user: Pubkey, allocation: Option<u64> },
+    /// Quita a `user` de la lista y su asignación; no procede si ya compró.
+    Remove { user: Pubkey },
+}
+
+/// Entrada de una wallet en la lista blanca explícita de una preventa, con su
+/// asignación explícita si la tiene. Al ser una PDA por wallet, la lista solo
+/// tiene el tope opcional `max_entries` de la preventa.
+#[account]
+#[derive(Default)]
+pub struct WhitelistEntry {
+    pub presale: Pubkey,
+    pub user: Pubkey,
+    pub whitelisted: bool,
+    /// Asignación fijada con `set_allocation`; reemplaza el tope por wallet.
+    pub allocation: Option<u64>,
+    pub bump: u8,
+}
+
+impl WhitelistEntry {
+    pub const SEED: &'static [u8] = b"whitelist_entry";
+
+    pub const LEN: usize = 8 + // discriminator
+        32 + // presale
+        32 + // user
+        1 + // whitelisted
+        (1 + 8) + // allocation
+        1; // bump
+
+    /// Una entrada recién creada por `init_if_needed` todavía no tiene usuario asignado.
+    pub fn is_new(&self) -> bool {
+        self.user == Pubkey::default()
+    }
+}
+
+impl Presale {
+    /// Si cabe una entrada más cuando ya hay `used` ocupadas.
+    pub fn has_room_for_entry(&self, used: u32) -> bool {
+        self.max_entries == 0 || (used as u64) < self.max_entries
+    }
+
+    /// Agrega la wallet de `entry` respetando `max_entries`; `false` si ya estaba.
+    pub fn add_whitelisted(&mut self, entry: &mut WhitelistEntry) -> Result<bool> {
+        if entry.whitelisted {
+            return Ok(false);
+        }
+        require!(self.has_room_for_entry(self.whitelist_size), PresaleError::InsufficientSpace);
+        entry.whitelisted = true;
+        self.whitelist_size = self.whitelist_size.checked_add(1).ok_or(PresaleError::CalculationError)?;
+        Ok(true)
+    }
+
+    pub fn remove_whitelisted(&mut self, entry: &mut WhitelistEntry) -> bool {
+        if !entry.whitelisted {
+            return false;
+        }
+        entry.whitelisted = false;
+        self.whitelist_size = self.whitelist_size.saturating_sub(1);
+        true
+    }
+}
 
 pub fn add_to_whitelist(ctx: Context<AddToWhitelist>, user: Pubkey) -> Result<()> {
     let sale = &mut ctx.accounts.sale;
-    if !sale.whitelist.contains(&user) {
-        sale.whitelist.push(user);
+    let whitelist_entry = &mut ctx.accounts.whitelist_entry;
+    if whitelist_entry.is_new() {
+        whitelist_entry.presale = sale.key();
+        whitelist_entry.user = user;
+        whitelist_entry.bump = ctx.bumps.whitelist_entry;
+    }
+    if sale.add_whitelisted(whitelist_entry)? {
+        emit!(WhitelistUpdated {
+            presale: sale.key(),
+            user,
+            added: true,
+            whitelist_size: sale.whitelist_size,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
     }
     Ok(())
 }
 
+/// Agrega, actualiza o quita varias entradas de la lista blanca y sus
+/// asignaciones. Tocar asignaciones exige además el rol de operador. No se puede
+/// quitar a quien ya compró ni bajar su límite por debajo de lo comprado: se
+/// verifica con su PDA de compra.
+///
+/// Por cada cambio, `remaining_accounts` lleva la `WhitelistEntry` del usuario,
+/// que se crea a costa del admin si no existe, seguida de su PDA de compra en
+/// las actualizaciones y bajas, aunque no exista.
+pub fn update_whitelist_batch<'info>(
+    ctx: Context<'_, '_, '_, 'info, UpdateWhitelistBatch<'info>>,
+    changes: Vec<WhitelistChange>,
+) -> Result<()> {
+    require!(
+        !changes.is_empty() && changes.len() <= MAX_WHITELIST_BATCH,
+        PresaleError::InvalidWhitelistBatch
+    );
+    let can_allocate = ctx.accounts.admin_role.has(Role::Operator);
+    let sale_key = ctx.accounts.sale.key();
+    let admin = ctx.accounts.admin.to_account_info();
+    let system_program = ctx.accounts.system_program.to_account_info();
+    let sale = &mut ctx.accounts.sale;
+    let timestamp = Clock::get()?.unix_timestamp;
+    let mut accounts = ctx.remaining_accounts.iter();
+
+    for change in changes {
+        let user = match change {
+            WhitelistChange::Add { user, .. } | WhitelistChange::Update { user, .. } | WhitelistChange::Remove { user } => user,
+        };
+        let entry_account = accounts.next().ok_or(PresaleError::InvalidWhitelistBatch)?;
+        let mut entry = load_whitelist_entry(entry_account, &admin, &system_program, &sale_key, &user, ctx.program_id)?;
+
+        let allocation_change = match change {
+            WhitelistChange::Add { allocation, .. } => {
+                if sale.add_whitelisted(&mut entry)? {
+                    emit!(WhitelistUpdated {
+                        presale: sale_key,
+                        user,
+                        added: true,
+                        whitelist_size: sale.whitelist_size,
+                        timestamp,
+                    });
+                }
+                allocation.map(Some)
+            }
+            WhitelistChange::Update { allocation, .. } => {
+                let purchase_record = accounts.next().ok_or(PresaleError::InvalidWhitelistBatch)?;
+                let tokens_purchased = tokens_purchased(purchase_record, &sale_key, &user, ctx.program_id)?;
+                if let Some(wallet_limit) = sale.wallet_limit(allocation) {
+                    require!(wallet_limit >= tokens_purchased, PresaleError::AllocationBelowPurchased);
+                }
+                Some(allocation)
+            }
+            WhitelistChange::Remove { .. } => {
+                let purchase_record = accounts.next().ok_or(PresaleError::InvalidWhitelistBatch)?;
+                let tokens_purchased = tokens_purchased(purchase_record, &sale_key, &user, ctx.program_id)?;
+                require!(tokens_purchased == 0, PresaleError::UserHasPurchased);
+
+                if sale.remove_whitelisted(&mut entry) {
+                    emit!(WhitelistUpdated {
+                        presale: sale_key,
+                        user,
+                        added: false,
+                        whitelist_size: sale.whitelist_size,
+                        timestamp,
+                    });
+                }
+                entry.allocation.map(|_| None)
+            }
+        };
+
+        match allocation_change {
+            Some(Some(allocation)) => {
+                require!(can_allocate, PresaleError::Unauthorized);
+                let previous_allocation = sale.set_explicit_allocation(&mut entry, allocation)?;
+                emit!(AllocationUpdated {
+                    presale: sale_key,
+                    user,
+                    previous_allocation,
+                    allocation,
+                    removed: false,
+                    timestamp,
+                });
+            }
+            Some(None) => {
+                require!(can_allocate, PresaleError::Unauthorized);
+                if let Some(previous_allocation) = sale.remove_explicit_allocation(&mut entry) {
+                    emit!(AllocationUpdated {
+                        presale: sale_key,
+                        user,
+                        previous_allocation,
+                        allocation: 0,
+                        removed: true,
+                        timestamp,
+                    });
+                }
+            }
+            None => {}
+        }
+
+        entry.try_serialize(&mut &mut entry_account.try_borrow_mut_data()?[..])?;
+    }
+    Ok(())
+}
+
+/// Tokens comprados por `user` según su PDA de compra; 0 si aún no existe.
+pub fn tokens_purchased(purchase_record: &AccountInfo, sale_key: &Pubkey, user: &Pubkey, program_id: &Pubkey) -> Result<u64> {
+    let (expected_record, _) = Pubkey::find_program_address(
+        &[PurchaseRecord::SEED, sale_key.as_ref(), user.as_ref()],
+        program_id,
+    );
+    require!(purchase_record.key() == expected_record, PresaleError::InvalidWhitelistBatch);
+    if purchase_record.owner != program_id || purchase_record.data_is_empty() {
+        return Ok(0);
+    }
+    let purchase_record = PurchaseRecord::try_deserialize(&mut &purchase_record.try_borrow_data()?[..])?;
+    Ok(purchase_record.tokens_purchased)
+}
+
+/// Lee la `WhitelistEntry` de `user` o, si la PDA todavía no existe, la crea
+/// con la renta a cargo de `payer`.
+fn load_whitelist_entry<'info>(
+    entry_account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    sale_key: &Pubkey,
+    user: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<WhitelistEntry> {
+    let (expected_entry, bump) = Pubkey::find_program_address(
+        &[WhitelistEntry::SEED, sale_key.as_ref(), user.as_ref()],
+        program_id,
+    );
+    require!(entry_account.key() == expected_entry, PresaleError::InvalidWhitelistBatch);
+    if entry_account.owner == program_id {
+        return WhitelistEntry::try_deserialize(&mut &entry_account.try_borrow_data()?[..]);
+    }
+
+    // Igual que `init`: la cuenta puede tener lamports previos, así que se
+    // completa la renta y luego se asigna al programa
+    let entry_seeds = &[WhitelistEntry::SEED, sale_key.as_ref(), user.as_ref(), &[bump]];
+    let signer = &[&entry_seeds[..]];
+    let rent_exempt_minimum = Rent::get()?.minimum_balance(WhitelistEntry::LEN);
+    let missing = rent_exempt_minimum.saturating_sub(entry_account.lamports());
+    if missing > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer { from: payer.clone(), to: entry_account.clone() },
+            ),
+            missing,
+        )?;
+    }
+    anchor_lang::system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Allocate { account_to_allocate: entry_account.clone() },
+            signer,
+        ),
+        WhitelistEntry::LEN as u64,
+    )?;
+    anchor_lang::system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Assign { account_to_assign: entry_account.clone() },
+            signer,
+        ),
+        program_id,
+    )?;
+
+    Ok(WhitelistEntry {
+        presale: *sale_key,
+        user: *user,
+        bump,
+        ..Default::default()
+    })
+}
+
+pub fn set_merkle_root(ctx: Context<SetMerkleRoot>, merkle_root: [u8; 32], merkle_allocations: bool) -> Result<()> {
+    let sale = &mut ctx.accounts.sale;
+
+    // La raíz solo puede rotarse antes de que empiece la venta
+    let current_time = Clock::get()?.unix_timestamp;
+    require!(current_time < sale.start_time, PresaleError::PresaleAlreadyStarted);
+
+    sale.merkle_root = merkle_root;
+    sale.merkle_allocations = merkle_allocations;
+
+    emit!(MerkleRootUpdated {
+        presale: sale.key(),
+        merkle_root,
+        merkle_allocations,
+        timestamp: current_time,
+    });
+    Ok(())
+}
+
+/// Hoja del árbol: `keccak(comprador)` o `keccak(comprador || asignación_le)`
+/// cuando la preventa asigna montos por hoja.
+pub fn merkle_leaf(user: &Pubkey, allocation: Option<u64>) -> [u8; 32] {
+    match allocation {
+        Some(allocation) => keccak::hashv(&[user.as_ref(), &allocation.to_le_bytes()]).to_bytes(),
+        None => keccak::hashv(&[user.as_ref()]).to_bytes(),
+    }
+}
+
+/// Verifica una prueba Merkle con pares ordenados, de modo que la prueba no
+/// necesita indicar la posición de cada nodo hermano.
+pub fn verify_merkle_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            keccak::hashv(&[&node, sibling]).to_bytes()
+        } else {
+            keccak::hashv(&[sibling, &node]).to_bytes()
+        }
+    });
+    computed == *root
+}
+
 #[derive(Accounts)]
+#[instruction(user: Pubkey)]
 pub struct AddToWhitelist<'info> {
     #[account(mut)]
     pub sale: Account<'info, Presale>,
+    #[account(mut)]
     pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::WhitelistManager) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = WhitelistEntry::LEN,
+        seeds = [WhitelistEntry::SEED, sale.key().as_ref(), user.as_ref()],
+        bump,
+    )]
+    pub whitelist_entry: Account<'info, WhitelistEntry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateWhitelistBatch<'info> {
+    #[account(mut)]
+    pub sale: Account<'info, Presale>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::WhitelistManager) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetMerkleRoot<'info> {
+    #[account(mut)]
+    pub sale: Account<'info, Presale>,
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [RoleAssignment::SEED, sale.key().as_ref(), admin.key().as_ref()],
+        bump = admin_role.bump,
+        constraint = admin_role.has(Role::WhitelistManager) @ PresaleError::Unauthorized,
+    )]
+    pub admin_role: Account<'info, RoleAssignment>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_pair(a: [u8; 32], b: [u8; 32]) -> [u8; 32] {
+        if a <= b {
+            keccak::hashv(&[&a, &b]).to_bytes()
+        } else {
+            keccak::hashv(&[&b, &a]).to_bytes()
+        }
+    }
+
+    /// Árbol de cuatro hojas; devuelve la raíz, las hojas y la prueba de la hoja 0.
+    fn tree(allocations: Option<[u64; 4]>) -> ([u8; 32], Vec<Pubkey>, Vec<[u8; 32]>) {
+        let users: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let leaves: Vec<[u8; 32]> = users.iter()
+            .enumerate()
+            .map(|(index, user)| merkle_leaf(user, allocations.map(|allocations| allocations[index])))
+            .collect();
+        let left = hash_pair(leaves[0], leaves[1]);
+        let right = hash_pair(leaves[2], leaves[3]);
+        (hash_pair(left, right), users, vec![leaves[1], right])
+    }
+
+    #[test]
+    fn valid_proof_is_accepted() {
+        let (root, users, proof) = tree(None);
+        assert!(verify_merkle_proof(&proof, &root, merkle_leaf(&users[0], None)));
+    }
+
+    #[test]
+    fn valid_proof_with_allocation_is_accepted() {
+        let (root, users, proof) = tree(Some([100, 200, 300, 400]));
+        assert!(verify_merkle_proof(&proof, &root, merkle_leaf(&users[0], Some(100))));
+    }
+
+    #[test]
+    fn wrong_leaf_is_rejected() {
+        let (root, users, proof) = tree(Some([100, 200, 300, 400]));
+        assert!(!verify_merkle_proof(&proof, &root, merkle_leaf(&Pubkey::new_unique(), Some(100))));
+        // El mismo comprador con otra asignación tampoco es válido
+        assert!(!verify_merkle_proof(&proof, &root, merkle_leaf(&users[0], Some(101))));
+        assert!(!verify_merkle_proof(&proof, &root, merkle_leaf(&users[0], None)));
+    }
+
+    #[test]
+    fn malformed_proof_is_rejected() {
+        let (root, users, proof) = tree(None);
+        let leaf = merkle_leaf(&users[0], None);
+        assert!(!verify_merkle_proof(&[], &root, leaf));
+        assert!(!verify_merkle_proof(&proof[..1], &root, leaf));
+        assert!(!verify_merkle_proof(&[proof[0], proof[1], proof[1]], &root, leaf));
+        assert!(!verify_merkle_proof(&[proof[1], proof[0]], &root, leaf));
+        assert!(!verify_merkle_proof(&[[0u8; 32], proof[1]], &root, leaf));
+    }
+
+    #[test]
+    fn whitelist_rejects_new_wallets_at_max_entries() {
+        let mut presale = Presale { max_entries: 2, ..Default::default() };
+        let mut entries: Vec<WhitelistEntry> = (0..3).map(|_| WhitelistEntry::default()).collect();
+        assert!(presale.add_whitelisted(&mut entries[0]).unwrap());
+        assert!(presale.add_whitelisted(&mut entries[1]).unwrap());
+        assert!(presale.add_whitelisted(&mut entries[2]).is_err());
+        assert!(!entries[2].whitelisted);
+        assert_eq!(presale.whitelist_size, 2);
+
+        // Volver a agregar a quien ya está no ocupa lugar, y una baja lo libera
+        assert!(!presale.add_whitelisted(&mut entries[0]).unwrap());
+        assert!(presale.remove_whitelisted(&mut entries[1]));
+        assert!(presale.add_whitelisted(&mut entries[2]).unwrap());
+    }
+
+    #[test]
+    fn whitelist_without_max_entries_has_no_cap() {
+        let mut presale = Presale::default();
+        for _ in 0..100 {
+            assert!(presale.add_whitelisted(&mut WhitelistEntry::default()).unwrap());
+        }
+        assert_eq!(presale.whitelist_size, 100);
+    }
 }